@@ -0,0 +1,22 @@
+// Compiles a program through the C API and prints the assembly.
+//
+//     cargo build --lib --release --features ffi
+//     gcc examples/ffi/harness.c -Iinclude -Ltarget/release -lzcc -o harness
+//     LD_LIBRARY_PATH=target/release ./harness
+#include <stdio.h>
+
+#include "zcc.h"
+
+int main(void) {
+    char *asm_text;
+    char *diagnostics;
+    int status = zcc_compile_to_asm("int main(void) { return 2; }", &asm_text, &diagnostics);
+    if (status == ZCC_OK) {
+        fputs(asm_text, stdout);
+    } else if (diagnostics) {
+        fprintf(stderr, "%s\n", diagnostics);
+    }
+    zcc_free_string(asm_text);
+    zcc_free_string(diagnostics);
+    return status;
+}
@@ -0,0 +1,49 @@
+/* C API of the zcc frontend, see src/ffi.rs. Generated by cbindgen, don't edit. */
+
+#ifndef ZCC_H
+#define ZCC_H
+
+#define ZCC_OK 0
+
+/**
+ * The source was rejected; the diagnostics explain why.
+ */
+#define ZCC_COMPILE_ERROR 1
+
+/**
+ * A null pointer or a source that isn't valid UTF-8 was passed in.
+ */
+#define ZCC_INVALID_ARGUMENT 2
+
+/**
+ * The compiler panicked, which is a bug in zcc.
+ */
+#define ZCC_INTERNAL_ERROR 3
+
+/**
+ * Compile the NUL-terminated C source `src` to assembly.
+ *
+ * On `ZCC_OK`, `*out_asm` points to the assembly, and when there are
+ * warnings, `*out_diagnostics_json` points to a JSON array of
+ * `{"message": ..., "code": ...}` objects. On `ZCC_COMPILE_ERROR` and
+ * `ZCC_INTERNAL_ERROR`, `*out_diagnostics_json` points to a JSON array of
+ * `{"message": ...}` objects. Outputs that aren't set are null. Free the
+ * ones that are with `zcc_free_string`.
+ *
+ * # Safety
+ *
+ * `src` must be null or point to a NUL-terminated string, and both out
+ * pointers must be null or valid for writing a pointer.
+ */
+int zcc_compile_to_asm(const char *src, char **out_asm, char **out_diagnostics_json);
+
+/**
+ * Release a string returned by this API. Null is ignored.
+ *
+ * # Safety
+ *
+ * `s` must be null or a pointer returned by this API that hasn't been freed.
+ */
+void zcc_free_string(char *s);
+
+#endif  /* ZCC_H */
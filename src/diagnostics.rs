@@ -0,0 +1,128 @@
+use std::io::IsTerminal;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum ColorChoice {
+    /// Color when stderr is a terminal, unless the environment says otherwise
+    Auto,
+    Always,
+    Never,
+}
+
+/// Decide whether diagnostics get ANSI colors, reading `CLICOLOR_FORCE`,
+/// `NO_COLOR` and whether stderr is a terminal.
+pub fn use_color(choice: ColorChoice) -> bool {
+    resolve_color(
+        choice,
+        std::env::var("CLICOLOR_FORCE").ok().as_deref(),
+        std::env::var("NO_COLOR").ok().as_deref(),
+        std::io::stderr().is_terminal(),
+    )
+}
+
+// Precedence: --color flag > CLICOLOR_FORCE > NO_COLOR > TTY detection.
+// Following the conventions, an empty NO_COLOR and a CLICOLOR_FORCE of "0"
+// count as unset.
+fn resolve_color(
+    choice: ColorChoice,
+    clicolor_force: Option<&str>,
+    no_color: Option<&str>,
+    is_tty: bool,
+) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            if clicolor_force.is_some_and(|v| !v.is_empty() && v != "0") {
+                true
+            } else if no_color.is_some_and(|v| !v.is_empty()) {
+                false
+            } else {
+                is_tty
+            }
+        }
+    }
+}
+
+/// A problem that doesn't stop compilation. The code stays the same across
+/// releases, so it can be named on the command line.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Warning {
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// A declaration without a type, accepted as int before C99
+pub const IMPLICIT_INT: &str = "W0001";
+/// A function returning a value that can end without a return statement
+pub const MISSING_RETURN: &str = "W0002";
+/// main returning a constant that doesn't fit in an exit status
+pub const EXIT_CODE_RANGE: &str = "W0003";
+/// Statements after a return
+pub const UNREACHABLE_CODE: &str = "W0004";
+
+/// Every warning code and what it's about, for reports that list them
+pub const CODES: [(&str, &str); 4] = [
+    (IMPLICIT_INT, "declaration without a type, accepted as int"),
+    (MISSING_RETURN, "non-void function can end without a return"),
+    (
+        EXIT_CODE_RANGE,
+        "main returns a constant that doesn't fit an exit status",
+    ),
+    (UNREACHABLE_CODE, "statement after a return"),
+];
+
+pub fn error(message: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[1;31merror:\x1b[0m {message}")
+    } else {
+        format!("error: {message}")
+    }
+}
+
+pub fn warning(message: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[1;35mwarning:\x1b[0m {message}")
+    } else {
+        format!("warning: {message}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_wins_over_the_environment() {
+        assert!(resolve_color(ColorChoice::Always, None, Some("1"), false));
+        assert!(!resolve_color(ColorChoice::Never, Some("1"), None, true));
+    }
+
+    #[test]
+    fn auto_reads_the_environment_then_the_terminal() {
+        assert!(resolve_color(ColorChoice::Auto, None, None, true));
+        assert!(!resolve_color(ColorChoice::Auto, None, None, false));
+        assert!(resolve_color(ColorChoice::Auto, Some("1"), None, false));
+        assert!(resolve_color(
+            ColorChoice::Auto,
+            Some("1"),
+            Some("1"),
+            false
+        ));
+        assert!(!resolve_color(ColorChoice::Auto, None, Some("1"), true));
+    }
+
+    #[test]
+    fn empty_no_color_and_zero_clicolor_force_are_unset() {
+        assert!(resolve_color(ColorChoice::Auto, None, Some(""), true));
+        assert!(!resolve_color(ColorChoice::Auto, Some("0"), None, false));
+        assert!(!resolve_color(ColorChoice::Auto, Some(""), Some("1"), true));
+    }
+
+    #[test]
+    fn colored_messages() {
+        assert_eq!(error("oops", false), "error: oops");
+        assert_eq!(error("oops", true), "\x1b[1;31merror:\x1b[0m oops");
+        assert_eq!(warning("hm", false), "warning: hm");
+    }
+}
@@ -0,0 +1,120 @@
+// C API for embedding the frontend without spawning processes. Build the
+// shared library with
+//
+//     cargo build --lib --release --features ffi
+//
+// and include include/zcc.h, which cbindgen generates from this file (see
+// tests/ffi.rs). Every string handed out by this API is owned by
+// the caller and must be released with zcc_free_string.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::diagnostics::Warning;
+use crate::{compile_source, json_escape, CompileError, CompileOptions};
+
+pub const ZCC_OK: c_int = 0;
+/// The source was rejected; the diagnostics explain why.
+pub const ZCC_COMPILE_ERROR: c_int = 1;
+/// A null pointer or a source that isn't valid UTF-8 was passed in.
+pub const ZCC_INVALID_ARGUMENT: c_int = 2;
+/// The compiler panicked, which is a bug in zcc.
+pub const ZCC_INTERNAL_ERROR: c_int = 3;
+
+/// Compile the NUL-terminated C source `src` to assembly.
+///
+/// On `ZCC_OK`, `*out_asm` points to the assembly, and when there are
+/// warnings, `*out_diagnostics_json` points to a JSON array of
+/// `{"message": ..., "code": ...}` objects. On `ZCC_COMPILE_ERROR` and
+/// `ZCC_INTERNAL_ERROR`, `*out_diagnostics_json` points to a JSON array of
+/// `{"message": ...}` objects. Outputs that aren't set are null. Free the
+/// ones that are with `zcc_free_string`.
+///
+/// # Safety
+///
+/// `src` must be null or point to a NUL-terminated string, and both out
+/// pointers must be null or valid for writing a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zcc_compile_to_asm(
+    src: *const c_char,
+    out_asm: *mut *mut c_char,
+    out_diagnostics_json: *mut *mut c_char,
+) -> c_int {
+    if out_asm.is_null() || out_diagnostics_json.is_null() {
+        return ZCC_INVALID_ARGUMENT;
+    }
+    *out_asm = ptr::null_mut();
+    *out_diagnostics_json = ptr::null_mut();
+    if src.is_null() {
+        return ZCC_INVALID_ARGUMENT;
+    }
+    let Ok(source) = CStr::from_ptr(src).to_str() else {
+        return ZCC_INVALID_ARGUMENT;
+    };
+
+    let options = CompileOptions::default();
+    match panic::catch_unwind(AssertUnwindSafe(|| compile_source(source, &options, None))) {
+        Ok(Ok(output)) => {
+            *out_asm = into_c_string(output.assembly);
+            if !output.warnings.is_empty() {
+                *out_diagnostics_json = into_c_string(warnings_json(&output.warnings));
+            }
+            ZCC_OK
+        }
+        Ok(Err(error @ CompileError::Internal(_))) => {
+            *out_diagnostics_json = into_c_string(diagnostics_json(&error.messages()));
+            ZCC_INTERNAL_ERROR
+        }
+        Ok(Err(error)) => {
+            *out_diagnostics_json = into_c_string(diagnostics_json(&error.messages()));
+            ZCC_COMPILE_ERROR
+        }
+        Err(_) => {
+            let errors = ["internal compiler error".to_string()];
+            *out_diagnostics_json = into_c_string(diagnostics_json(&errors));
+            ZCC_INTERNAL_ERROR
+        }
+    }
+}
+
+/// Release a string returned by this API. Null is ignored.
+///
+/// # Safety
+///
+/// `s` must be null or a pointer returned by this API that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn zcc_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+fn into_c_string(text: String) -> *mut c_char {
+    // Neither assembly nor our JSON contain NUL bytes.
+    CString::new(text)
+        .expect("output should not contain NUL")
+        .into_raw()
+}
+
+fn diagnostics_json(errors: &[String]) -> String {
+    let messages: Vec<String> = errors
+        .iter()
+        .map(|e| format!("{{\"message\":\"{}\"}}", json_escape(e)))
+        .collect();
+    format!("[{}]", messages.join(","))
+}
+
+fn warnings_json(warnings: &[Warning]) -> String {
+    let warnings: Vec<String> = warnings
+        .iter()
+        .map(|warning| {
+            format!(
+                "{{\"message\":\"{}\",\"code\":\"{}\"}}",
+                json_escape(&warning.message),
+                warning.code
+            )
+        })
+        .collect();
+    format!("[{}]", warnings.join(","))
+}
@@ -0,0 +1,253 @@
+use crate::{
+    lexer, parse_program, Child, NodeId, Parser, Symbol, SyntaxTree, Token, TokenKind, TreeKind,
+};
+
+const INDENT: usize = 4;
+
+// Why a source couldn't be formatted.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FormatError {
+    // It contains characters the lexer has no token for.
+    Lex,
+    // It has comments, which the tree doesn't keep.
+    Comments,
+    // It doesn't parse, with the parser's errors.
+    Parse(Vec<String>),
+}
+
+// Format C source text, refusing anything that doesn't lex and parse cleanly.
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    let tokens = lexer(source.into());
+    if tokens.is_err() {
+        return Err(FormatError::Lex);
+    }
+    if tokens.has_comments() {
+        return Err(FormatError::Comments);
+    }
+    let mut parser = Parser::new(tokens);
+    parse_program(&mut parser);
+    if !parser.errors.is_empty() {
+        return Err(FormatError::Parse(parser.errors));
+    }
+    Ok(format_tree(&parser.build_tree()))
+}
+
+// Re-emit the source of a syntax tree in the canonical layout:
+//
+// int main(void) {
+//     return 2;
+// }
+//
+// Trees containing an ErrorTree should be refused by the caller, since the
+// tokens of a broken program can't be placed reliably.
+pub fn format_tree(tree: &SyntaxTree) -> String {
+    let mut output = String::new();
+    format_node(tree, tree.root(), 0, &mut output);
+    output
+}
+
+fn format_node(tree: &SyntaxTree, id: NodeId, depth: usize, output: &mut String) {
+    match tree.kind(id) {
+        TreeKind::Program => {
+            for (i, child) in tree.children(id).iter().enumerate() {
+                if let Child::Tree(item) = child {
+                    if i > 0 {
+                        output.push('\n');
+                    }
+                    format_node(tree, *item, depth, output);
+                }
+            }
+        }
+        TreeKind::Function => {
+            let mut previous = None;
+            for child in tree.children(id) {
+                match child {
+                    Child::Token(t) => {
+                        let token = tree.token(*t);
+                        match token.kind {
+                            TokenKind::OpenBrace => output.push_str(" {\n"),
+                            TokenKind::CloseBrace => {
+                                output.push_str(&" ".repeat(depth));
+                                output.push_str("}\n");
+                            }
+                            _ => {
+                                if previous.is_some_and(|previous| space_between(previous, token)) {
+                                    output.push(' ');
+                                }
+                                output.push_str(tree.text(token));
+                            }
+                        }
+                        previous = Some(token);
+                    }
+                    // The parts of the head on the same line as the name.
+                    Child::Tree(head)
+                        if matches!(tree.kind(*head), TreeKind::StructType | TreeKind::Params) =>
+                    {
+                        format_inline(tree, *head, &mut previous, output)
+                    }
+                    Child::Tree(statement) => format_node(tree, *statement, depth + INDENT, output),
+                }
+            }
+        }
+        TreeKind::StructDecl => {
+            output.push_str(&" ".repeat(depth));
+            let mut previous = None;
+            for child in tree.children(id) {
+                match child {
+                    Child::Token(t) => {
+                        let token = tree.token(*t);
+                        match token.kind {
+                            TokenKind::OpenBrace => output.push_str(" {\n"),
+                            TokenKind::CloseBrace => {
+                                output.push_str(&" ".repeat(depth));
+                                output.push('}');
+                            }
+                            TokenKind::Semicolon => output.push_str(";\n"),
+                            _ => {
+                                if previous.is_some_and(|previous| space_between(previous, token)) {
+                                    output.push(' ');
+                                }
+                                output.push_str(tree.text(token));
+                            }
+                        }
+                        previous = Some(token);
+                    }
+                    Child::Tree(member) => format_node(tree, *member, depth + INDENT, output),
+                }
+            }
+        }
+        TreeKind::ImplicitInt
+        | TreeKind::Unary
+        | TreeKind::Paren
+        | TreeKind::StructType
+        | TreeKind::Params
+        | TreeKind::Param => {}
+        TreeKind::Prototype
+        | TreeKind::GlobalVar
+        | TreeKind::MemberDecl
+        | TreeKind::Return
+        | TreeKind::InlineAsm
+        | TreeKind::ErrorTree => {
+            output.push_str(&" ".repeat(depth));
+            format_inline(tree, id, &mut None, output);
+            output.push('\n');
+        }
+    }
+}
+
+// Print every token below `id` on the current line.
+fn format_inline<'a>(
+    tree: &'a SyntaxTree,
+    id: NodeId,
+    previous: &mut Option<&'a Token>,
+    output: &mut String,
+) {
+    for child in tree.children(id) {
+        match child {
+            Child::Token(t) => {
+                let token = tree.token(*t);
+                if previous.is_some_and(|previous| space_between(previous, token)) {
+                    output.push(' ');
+                }
+                output.push_str(tree.text(token));
+                *previous = Some(token);
+            }
+            Child::Tree(t) => format_inline(tree, *t, previous, output),
+        }
+    }
+}
+
+fn space_between(previous: &Token, next: &Token) -> bool {
+    let (previous_kind, next_kind) = (previous.kind, next.kind);
+    // Without the space `- -2` would lex as a decrement.
+    if (previous_kind, next_kind) == (TokenKind::Minus, TokenKind::Minus) {
+        return true;
+    }
+    // `__asm__("nop")` is written like a call, unlike `return (2)`.
+    if previous.text == Symbol::ASM && next_kind == TokenKind::OpenParen {
+        return false;
+    }
+    !matches!(
+        (previous_kind, next_kind),
+        (TokenKind::OpenParen, _)
+            | (
+                _,
+                TokenKind::CloseParen | TokenKind::Semicolon | TokenKind::Comma
+            )
+            | (TokenKind::Identifier, TokenKind::OpenParen)
+            | (TokenKind::Bang | TokenKind::Minus | TokenKind::Tilde, _)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messy_program_gets_the_canonical_layout() {
+        let messy = "int   g=3;int h;\nint foo(void);\nint\nmain ( void ){__asm__(\"nop\");\
+                     return -(~ 2) ;   }\nvoid f(void){return;}\n";
+        assert_eq!(
+            format_source(messy).unwrap(),
+            "int g = 3;\n\nint h;\n\nint foo(void);\n\nint main(void) {\n    __asm__(\"nop\");\n    \
+             return -(~2);\n}\n\nvoid f(void) {\n    return;\n}\n"
+        );
+    }
+
+    #[test]
+    fn parameters_are_separated_by_a_comma_and_a_space() {
+        assert_eq!(
+            format_source("int f( int a ,int b ,struct s c ) { return 1; }\nint g(int,int);")
+                .unwrap(),
+            "int f(int a, int b, struct s c) {\n    return 1;\n}\n\nint g(int, int);\n"
+        );
+    }
+
+    fn corpus(dir: &std::path::Path, sources: &mut Vec<(String, String)>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                corpus(&path, sources);
+            } else if path.extension().is_some_and(|extension| extension == "c") {
+                let text = std::fs::read_to_string(&path).unwrap();
+                // Drop the expectation comments of tests/cases.
+                let code = text.lines().skip_while(|line| line.starts_with("//"));
+                let code = code.map(|line| format!("{line}\n")).collect();
+                sources.push((path.display().to_string(), code));
+            }
+        }
+    }
+
+    #[test]
+    fn formatting_is_idempotent_over_the_fixtures() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut sources = vec![];
+        corpus(&root.join("examples"), &mut sources);
+        corpus(&root.join("tests/cases"), &mut sources);
+        let mut formatted = 0;
+        for (path, source) in sources {
+            let Ok(once) = format_source(&source) else {
+                continue;
+            };
+            assert_eq!(format_source(&once), Ok(once.clone()), "{path}");
+            formatted += 1;
+        }
+        assert!(formatted >= 10, "only {formatted} fixtures formatted");
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_keep() {
+        assert_eq!(
+            format_source("int main(void) { return @; }"),
+            Err(FormatError::Lex)
+        );
+        assert_eq!(
+            format_source("// hi\nint main(void) { return 2; }"),
+            Err(FormatError::Comments)
+        );
+        assert_eq!(
+            format_source("int main(void) { return 2 }"),
+            Err(FormatError::Parse(vec!["1:27: expected Semicolon".into()]))
+        );
+    }
+}
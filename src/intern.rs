@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+// A handle to a string owned by an `Interner`. Two symbols from the same
+// interner are equal exactly when their strings are.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Default)]
+pub struct Symbol(u32);
+
+// Spellings every compilation needs are interned up front so tokens for
+// punctuation and keywords can be built without an interner at hand. The
+// order here must match the constants below.
+const PREDEFINED: &[&str] = &[
+    "", "(", ")", "{", "}", ";", "int", "void", "return", "=", "__asm__", "!", "!=", "-", "~",
+    "--", "+", "*", "/", "%", "&&", "||", "<", ">", "<=", ">=", "==", "&", "|", "^", "<<", ">>",
+    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<=", ">>=", "struct", ",",
+];
+
+impl Symbol {
+    pub const EMPTY: Symbol = Symbol(0);
+    pub const OPEN_PAREN: Symbol = Symbol(1);
+    pub const CLOSE_PAREN: Symbol = Symbol(2);
+    pub const OPEN_BRACE: Symbol = Symbol(3);
+    pub const CLOSE_BRACE: Symbol = Symbol(4);
+    pub const SEMICOLON: Symbol = Symbol(5);
+    pub const INT: Symbol = Symbol(6);
+    pub const VOID: Symbol = Symbol(7);
+    pub const RETURN: Symbol = Symbol(8);
+    pub const EQUALS: Symbol = Symbol(9);
+    pub const ASM: Symbol = Symbol(10);
+    pub const BANG: Symbol = Symbol(11);
+    pub const NOT_EQUAL: Symbol = Symbol(12);
+    pub const MINUS: Symbol = Symbol(13);
+    pub const TILDE: Symbol = Symbol(14);
+    pub const DECREMENT: Symbol = Symbol(15);
+    pub const PLUS: Symbol = Symbol(16);
+    pub const ASTERISK: Symbol = Symbol(17);
+    pub const SLASH: Symbol = Symbol(18);
+    pub const PERCENT: Symbol = Symbol(19);
+    pub const LOGICAL_AND: Symbol = Symbol(20);
+    pub const LOGICAL_OR: Symbol = Symbol(21);
+    pub const LESS: Symbol = Symbol(22);
+    pub const GREATER: Symbol = Symbol(23);
+    pub const LESS_EQUAL: Symbol = Symbol(24);
+    pub const GREATER_EQUAL: Symbol = Symbol(25);
+    pub const EQUAL_EQUAL: Symbol = Symbol(26);
+    pub const AMPERSAND: Symbol = Symbol(27);
+    pub const PIPE: Symbol = Symbol(28);
+    pub const CARET: Symbol = Symbol(29);
+    pub const SHIFT_LEFT: Symbol = Symbol(30);
+    pub const SHIFT_RIGHT: Symbol = Symbol(31);
+    pub const PLUS_EQUAL: Symbol = Symbol(32);
+    pub const MINUS_EQUAL: Symbol = Symbol(33);
+    pub const ASTERISK_EQUAL: Symbol = Symbol(34);
+    pub const SLASH_EQUAL: Symbol = Symbol(35);
+    pub const PERCENT_EQUAL: Symbol = Symbol(36);
+    pub const AMPERSAND_EQUAL: Symbol = Symbol(37);
+    pub const PIPE_EQUAL: Symbol = Symbol(38);
+    pub const CARET_EQUAL: Symbol = Symbol(39);
+    pub const SHIFT_LEFT_EQUAL: Symbol = Symbol(40);
+    pub const SHIFT_RIGHT_EQUAL: Symbol = Symbol(41);
+    pub const STRUCT: Symbol = Symbol(42);
+    pub const COMMA: Symbol = Symbol(43);
+}
+
+// Each string in `strings` at the index of its symbol, and the symbol of each
+// string in `symbols`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Interner {
+    strings: Vec<String>,
+    symbols: HashMap<String, Symbol>,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        let mut interner = Interner {
+            strings: Vec::new(),
+            symbols: HashMap::new(),
+        };
+        for text in PREDEFINED {
+            interner.intern(text);
+        }
+        interner
+    }
+}
+
+impl Interner {
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(text) {
+            return symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        self.strings.push(text.to_string());
+        self.symbols.insert(text.to_string(), symbol);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predefined_symbols() {
+        let interner = Interner::default();
+        assert_eq!(interner.resolve(Symbol::EMPTY), "");
+        assert_eq!(interner.resolve(Symbol::RETURN), "return");
+        assert_eq!(interner.resolve(Symbol::STRUCT), "struct");
+        assert_eq!(interner.resolve(Symbol::COMMA), ",");
+        assert_eq!(PREDEFINED.len(), Symbol::COMMA.0 as usize + 1);
+    }
+
+    #[test]
+    fn same_string_same_symbol() {
+        let mut interner = Interner::default();
+        let main = interner.intern("main");
+        let x = interner.intern("x");
+        assert_ne!(main, x);
+        assert_eq!(interner.intern("main"), main);
+        assert_eq!(interner.intern("int"), Symbol::INT);
+        assert_eq!(interner.resolve(main), "main");
+        assert_eq!(interner.resolve(x), "x");
+    }
+}
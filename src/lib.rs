@@ -0,0 +1,3689 @@
+use crate::diagnostics::Warning;
+use crate::tacky::{
+    generate_tacky, TackyBinaryOperator, TackyFunction, TackyInstruction, TackyProgram,
+    TackyUnaryOperator, TackyVal,
+};
+use regex::Regex;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+use std::io;
+use std::ops::Index;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+pub mod diagnostics;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod format;
+pub mod intern;
+pub mod passes;
+pub mod pretty;
+pub mod sarif;
+pub mod sha256;
+pub mod tacky;
+pub mod wasm;
+
+pub use intern::{Interner, Symbol};
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum TokenKind {
+    Identifier,
+    Constant,
+    StringLiteral,
+    Keyword,
+    OpenParen,
+    CloseParen,
+    OpenBrace,
+    CloseBrace,
+    Semicolon,
+    Comma,
+    Equals,
+    Bang,
+    NotEqual,
+    Minus,
+    Tilde,
+    Decrement,
+    Plus,
+    Asterisk,
+    Slash,
+    Percent,
+    LogicalAnd,
+    LogicalOr,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    EqualEqual,
+    Ampersand,
+    Pipe,
+    Caret,
+    ShiftLeft,
+    ShiftRight,
+    PlusEqual,
+    MinusEqual,
+    AsteriskEqual,
+    SlashEqual,
+    PercentEqual,
+    AmpersandEqual,
+    PipeEqual,
+    CaretEqual,
+    ShiftLeftEqual,
+    ShiftRightEqual,
+    Eof,
+    ErrorToken,
+}
+
+// Where a token starts in the source, counting from 1. Columns count
+// characters, so a tab takes one column like any other. Lines and the file
+// follow the preprocessor's line markers; without any, the file is empty.
+// The offset is in bytes from the start of the text that was lexed, from 0,
+// and ignores line markers.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct Span {
+    pub file: Symbol,
+    pub line: u32,
+    pub col: u32,
+    pub offset: usize,
+}
+
+// `f.c:12:3` for error messages, or `12:3` when the file isn't known.
+pub(crate) fn location(interner: &Interner, span: Span) -> String {
+    match interner.resolve(span.file) {
+        "" => format!("{}:{}", span.line, span.col),
+        file => format!("{file}:{}:{}", span.line, span.col),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: Symbol,
+    pub span: Span,
+}
+
+impl Token {
+    fn new(kind: TokenKind, text: Symbol) -> Self {
+        Token {
+            kind,
+            text,
+            span: Span::default(),
+        }
+    }
+    fn constant(text: Symbol) -> Self {
+        Self::new(TokenKind::Constant, text)
+    }
+    fn string_literal(text: Symbol) -> Self {
+        Self::new(TokenKind::StringLiteral, text)
+    }
+    fn keyword(text: Symbol) -> Self {
+        Self::new(TokenKind::Keyword, text)
+    }
+    fn identifier(text: Symbol) -> Self {
+        Self::new(TokenKind::Identifier, text)
+    }
+    fn error() -> Self {
+        Self::new(TokenKind::ErrorToken, Symbol::EMPTY)
+    }
+    // fn eof() -> Self {
+    //     Self::new(TokenKind::Eof, Symbol::EMPTY)
+    // }
+}
+
+// The lexer's output: the tokens together with the interner their text
+// symbols point into.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct TokenStream {
+    tokens: Vec<Token>,
+    interner: Interner,
+    errors: Vec<String>,
+    comments: usize,
+}
+
+impl TokenStream {
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, Token> {
+        self.tokens.iter()
+    }
+    pub fn as_slice(&self) -> &[Token] {
+        &self.tokens
+    }
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+    pub fn interner_mut(&mut self) -> &mut Interner {
+        &mut self.interner
+    }
+    pub fn text(&self, token: &Token) -> &str {
+        self.interner.resolve(token.text)
+    }
+    /// Whether the lexer hit any input it had no token for.
+    pub fn is_err(&self) -> bool {
+        self.tokens.iter().any(|t| t.kind == TokenKind::ErrorToken)
+    }
+    /// Why the error tokens are there, one located message each.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+    /// Whether the lexer skipped any comments, which leave no tokens.
+    pub fn has_comments(&self) -> bool {
+        self.comments > 0
+    }
+    pub fn texts(&self) -> Vec<&str> {
+        self.tokens.iter().map(|t| self.text(t)).collect()
+    }
+    pub fn kinds(&self) -> Vec<TokenKind> {
+        self.tokens.iter().map(|t| t.kind).collect()
+    }
+}
+
+impl TokenStream {
+    fn push(&mut self, token: Token, span: Span) {
+        self.tokens.push(Token { span, ..token });
+    }
+
+    // Lex the string literal `input` starts with and return how many bytes it
+    // took. The token's text keeps the quotes and escapes as written. A
+    // literal must end on its line; one that doesn't takes the rest of the
+    // line with it as an error.
+    fn push_string_literal(&mut self, input: &str, span: Span) -> usize {
+        let mut escaped = false;
+        for (i, c) in input.char_indices().skip(1) {
+            match c {
+                '\n' => break,
+                '"' if !escaped => {
+                    let symbol = self.interner.intern(&input[..=i]);
+                    self.push(Token::string_literal(symbol), span);
+                    return i + 1;
+                }
+                _ => escaped = c == '\\' && !escaped,
+            }
+        }
+        let at = location(&self.interner, span);
+        self.errors
+            .push(format!("{at}: missing terminating \" character"));
+        self.push(Token::error(), span);
+        input.find('\n').unwrap_or(input.len())
+    }
+
+    // Skip the comment `input` starts with, if it starts with one, and return
+    // how many bytes it took. A block comment that never ends is an error
+    // taking the rest of the input.
+    fn skip_comment(&mut self, input: &str, span: Span) -> Option<usize> {
+        if let Some(len) = comment_len(input) {
+            self.comments += 1;
+            return Some(len);
+        }
+        if !input.starts_with("/*") {
+            return None;
+        }
+        let at = location(&self.interner, span);
+        self.errors.push(format!("{at}: unterminated comment"));
+        self.push(Token::error(), span);
+        Some(input.len())
+    }
+
+    // An error token for the character `input` starts with, which no rule
+    // matches.
+    fn push_unexpected(&mut self, input: &str, span: Span) {
+        let c = input.chars().next().unwrap_or_default();
+        let at = location(&self.interner, span);
+        self.errors
+            .push(format!("{at}: unexpected character '{c}'"));
+        self.push(Token::error(), span);
+    }
+
+    fn push_identifier(&mut self, text: &str, max_identifier_len: usize, span: Span) {
+        if text.len() > max_identifier_len {
+            self.errors.push(format!(
+                "{}: identifier too long: {} bytes, the limit is {max_identifier_len}",
+                location(&self.interner, span),
+                text.len()
+            ));
+            self.push(Token::error(), span);
+        } else {
+            let symbol = self.interner.intern(text);
+            self.push(Token::identifier(symbol), span);
+        }
+    }
+
+    // Skip the line marker `input` starts with, if it starts with one, and
+    // return how many bytes it took. Positions after it count from the line
+    // and file it names.
+    fn skip_line_marker(
+        &mut self,
+        input: &str,
+        offset: usize,
+        position: &mut Position,
+    ) -> Option<usize> {
+        let (len, line, file) = line_marker(input)?;
+        let file = self.interner.intern(file);
+        position.jump(offset + len, line, file);
+        Some(len)
+    }
+}
+
+// The preprocessor puts `# 12 "f.c" 2` in front of the line it says is line
+// 12 of f.c. Gives the length of the marker with its newline, the line and
+// the file, if `input` starts with one.
+fn line_marker(input: &str) -> Option<(usize, u32, &str)> {
+    let marker = input.split('\n').next()?;
+    let (line, rest) = marker.strip_prefix("# ")?.split_once(' ')?;
+    let line = line.parse().ok()?;
+    let file = rest.strip_prefix('"')?;
+    let file = &file[..file.rfind('"')?];
+    Some(((marker.len() + 1).min(input.len()), line, file))
+}
+
+// The length of the comment `input` starts with. A line comment ends before
+// its newline; a block comment without its `*/` isn't a comment at all.
+fn comment_len(input: &str) -> Option<usize> {
+    if input.starts_with("//") {
+        Some(input.find('\n').unwrap_or(input.len()))
+    } else if let Some(body) = input.strip_prefix("/*") {
+        body.find("*/").map(|end| end + 4)
+    } else {
+        None
+    }
+}
+
+// Keyword "int"
+// Identifier "main"
+// OpenParen "("
+// ...
+impl fmt::Display for TokenStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            writeln!(f, "{:?} {:?}", token.kind, self.text(token))?;
+        }
+        Ok(())
+    }
+}
+
+impl Index<usize> for TokenStream {
+    type Output = Token;
+
+    fn index(&self, index: usize) -> &Token {
+        &self.tokens[index]
+    }
+}
+
+impl IntoIterator for TokenStream {
+    type Item = Token;
+    type IntoIter = std::vec::IntoIter<Token>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tokens.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TokenStream {
+    type Item = &'a Token;
+    type IntoIter = std::slice::Iter<'a, Token>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tokens.iter()
+    }
+}
+
+// How many tokens of each kind there are.
+pub fn token_stats(tokens: &[Token]) -> BTreeMap<TokenKind, usize> {
+    let mut stats = BTreeMap::new();
+    for token in tokens {
+        *stats.entry(token.kind).or_default() += 1;
+    }
+    stats
+}
+
+// Identifiers longer than this are refused, so a runaway one never reaches
+// the interner or the assembler.
+pub const MAX_IDENTIFIER_LEN: usize = 4096;
+
+// Expressions nested deeper than this are refused, so the passes that
+// recurse over the tree can't run out of stack.
+pub const MAX_NESTING: usize = 256;
+
+// The lexer's work on `source` step by step, following the algorithm in
+// `lexer`: which rule produced each token of `tokens` and what input was left
+// after it.
+//
+// Keyword "int"            ^(void|int|return|__asm__|struct)\b rest: " x;"
+// trim whitespace                                              rest: "x;"
+// Identifier "x"           ^([a-zA-Z_]\w*)\b                   rest: ";"
+//
+// The rule column is as wide as the longest rule, KEYWORD_RULE.
+pub fn explain_tokens(source: &str, tokens: &TokenStream) -> String {
+    const RULE: usize = KEYWORD_RULE.len();
+    const STEP: usize = 25 + RULE;
+    let mut output = String::new();
+    let mut rest = source;
+    for token in tokens {
+        loop {
+            let trimmed = rest.trim_start();
+            if trimmed.len() != rest.len() {
+                rest = trimmed;
+                writeln!(output, "{:<STEP$} rest: {rest:?}", "trim whitespace").unwrap();
+            } else if let Some(len) = comment_len(rest) {
+                rest = &rest[len..];
+                writeln!(output, "{:<STEP$} rest: {rest:?}", "skip comment").unwrap();
+            } else if let Some((len, ..)) = line_marker(rest).filter(|_| {
+                let before = &source[..source.len() - rest.len()];
+                before.is_empty() || before.ends_with('\n')
+            }) {
+                rest = &rest[len..];
+                writeln!(output, "{:<STEP$} rest: {rest:?}", "skip line marker").unwrap();
+            } else {
+                break;
+            }
+        }
+        let text = tokens.text(token);
+        let (rule, len) = match token.kind {
+            TokenKind::Keyword => (KEYWORD_RULE, text.len()),
+            TokenKind::Constant => (CONSTANT_RULE, text.len()),
+            TokenKind::Identifier => (IDENTIFIER_RULE, text.len()),
+            TokenKind::StringLiteral => ("string literal", text.len()),
+            TokenKind::ErrorToken => {
+                // Error tokens have no text, so find out again how much
+                // input the lexer gave up on.
+                let len = if rest.starts_with("/*") {
+                    rest.len()
+                } else if rest.starts_with('"') {
+                    rest.find('\n').unwrap_or(rest.len())
+                } else if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                    rest.find(|c: char| !c.is_alphanumeric() && c != '_')
+                        .unwrap_or(rest.len())
+                } else {
+                    rest.chars().next().map_or(0, char::len_utf8)
+                };
+                ("no rule matches", len)
+            }
+            _ => ("punctuation", text.len()),
+        };
+        let (matched, after) = rest.split_at(len);
+        rest = after;
+        let token = format!("{:?} {matched:?}", token.kind);
+        writeln!(output, "{token:<24} {rule:<RULE$} rest: {rest:?}").unwrap();
+    }
+    output
+}
+
+pub fn lexer(text: String) -> TokenStream {
+    lex_with_limit(text, MAX_IDENTIFIER_LEN)
+}
+
+// Like `lexer`, with identifiers of up to `max_identifier_len` bytes.
+pub fn lex_with_limit(text: String, max_identifier_len: usize) -> TokenStream {
+    // while input isn't empty:
+    //   if input starts with whitespace:
+    //     trim whitespace from start of input
+    //   else:
+    //     find longest match at start of input for any regex in Table 1-1
+    //     if no match is found, raise an error
+    //     convert matching substring into a token
+    //     remove matching substring from start of input
+    let mut stream = TokenStream::default();
+    lex_into(&text, &mut stream, max_identifier_len);
+    // token.push(Token::eof());
+    stream
+}
+
+fn lex_into(text: &str, stream: &mut TokenStream, max_identifier_len: usize) {
+    if text.is_ascii() {
+        lex_ascii(text, stream, max_identifier_len);
+    } else {
+        lex_chars(text, stream, max_identifier_len);
+    }
+}
+
+// Every operator and separator, longest first, so the first one the input
+// starts with is the longest match: `<<=` before `<<` before `<`.
+const PUNCTUATORS: &[(&str, TokenKind, Symbol)] = &[
+    ("<<=", TokenKind::ShiftLeftEqual, Symbol::SHIFT_LEFT_EQUAL),
+    (">>=", TokenKind::ShiftRightEqual, Symbol::SHIFT_RIGHT_EQUAL),
+    ("--", TokenKind::Decrement, Symbol::DECREMENT),
+    ("==", TokenKind::EqualEqual, Symbol::EQUAL_EQUAL),
+    ("!=", TokenKind::NotEqual, Symbol::NOT_EQUAL),
+    ("<=", TokenKind::LessEqual, Symbol::LESS_EQUAL),
+    (">=", TokenKind::GreaterEqual, Symbol::GREATER_EQUAL),
+    ("&&", TokenKind::LogicalAnd, Symbol::LOGICAL_AND),
+    ("||", TokenKind::LogicalOr, Symbol::LOGICAL_OR),
+    ("<<", TokenKind::ShiftLeft, Symbol::SHIFT_LEFT),
+    (">>", TokenKind::ShiftRight, Symbol::SHIFT_RIGHT),
+    ("+=", TokenKind::PlusEqual, Symbol::PLUS_EQUAL),
+    ("-=", TokenKind::MinusEqual, Symbol::MINUS_EQUAL),
+    ("*=", TokenKind::AsteriskEqual, Symbol::ASTERISK_EQUAL),
+    ("/=", TokenKind::SlashEqual, Symbol::SLASH_EQUAL),
+    ("%=", TokenKind::PercentEqual, Symbol::PERCENT_EQUAL),
+    ("&=", TokenKind::AmpersandEqual, Symbol::AMPERSAND_EQUAL),
+    ("|=", TokenKind::PipeEqual, Symbol::PIPE_EQUAL),
+    ("^=", TokenKind::CaretEqual, Symbol::CARET_EQUAL),
+    ("(", TokenKind::OpenParen, Symbol::OPEN_PAREN),
+    (")", TokenKind::CloseParen, Symbol::CLOSE_PAREN),
+    ("{", TokenKind::OpenBrace, Symbol::OPEN_BRACE),
+    ("}", TokenKind::CloseBrace, Symbol::CLOSE_BRACE),
+    (";", TokenKind::Semicolon, Symbol::SEMICOLON),
+    ("=", TokenKind::Equals, Symbol::EQUALS),
+    ("!", TokenKind::Bang, Symbol::BANG),
+    ("-", TokenKind::Minus, Symbol::MINUS),
+    ("~", TokenKind::Tilde, Symbol::TILDE),
+    ("+", TokenKind::Plus, Symbol::PLUS),
+    ("*", TokenKind::Asterisk, Symbol::ASTERISK),
+    ("/", TokenKind::Slash, Symbol::SLASH),
+    ("%", TokenKind::Percent, Symbol::PERCENT),
+    ("<", TokenKind::Less, Symbol::LESS),
+    (">", TokenKind::Greater, Symbol::GREATER),
+    ("&", TokenKind::Ampersand, Symbol::AMPERSAND),
+    ("|", TokenKind::Pipe, Symbol::PIPE),
+    ("^", TokenKind::Caret, Symbol::CARET),
+    (",", TokenKind::Comma, Symbol::COMMA),
+];
+
+fn punctuator(input: &str) -> Option<&'static (&'static str, TokenKind, Symbol)> {
+    PUNCTUATORS
+        .iter()
+        .find(|(punctuator, ..)| input.starts_with(punctuator))
+}
+
+// The rules `lex_chars` matches words and numbers with, compiled on first use.
+const KEYWORD_RULE: &str = r"^(void|int|return|__asm__|struct)\b";
+const CONSTANT_RULE: &str = r"^([0-9]+)\b";
+const IDENTIFIER_RULE: &str = r"^([a-zA-Z_]\w*)\b";
+static KEYWORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(KEYWORD_RULE).unwrap());
+static CONSTANT: LazyLock<Regex> = LazyLock::new(|| Regex::new(CONSTANT_RULE).unwrap());
+static IDENTIFIER: LazyLock<Regex> = LazyLock::new(|| Regex::new(IDENTIFIER_RULE).unwrap());
+
+fn lex_chars(text: &str, stream: &mut TokenStream, max_identifier_len: usize) {
+    let mut input = text;
+    let mut position = Position::new(text);
+    while !input.is_empty() {
+        let char = input.chars().next().expect("Should have had a character");
+        let span = position.at(text.len() - input.len());
+
+        if char.is_whitespace() {
+        } else if let Some(len) = stream.skip_comment(input, span) {
+            input = &input[len..];
+            continue;
+        } else if let Some(len) = (char == '#' && span.col == 1)
+            .then(|| stream.skip_line_marker(input, text.len() - input.len(), &mut position))
+            .flatten()
+        {
+            input = &input[len..];
+            continue;
+        } else if let Some(&(punctuator, kind, symbol)) = punctuator(input) {
+            stream.push(Token::new(kind, symbol), span);
+            input = &input[punctuator.len()..];
+            continue;
+        } else if char == '"' {
+            input = &input[stream.push_string_literal(input, span)..];
+            continue;
+        } else {
+            if CONSTANT.is_match(input) {
+                let caps = CONSTANT.captures(input).unwrap();
+                let matched_const = caps.get(0).unwrap().as_str();
+                input = &input[matched_const.len()..];
+                let symbol = stream.interner.intern(matched_const);
+                stream.push(Token::constant(symbol), span);
+                continue;
+            } else if IDENTIFIER.is_match(input) {
+                if KEYWORD.is_match(input) {
+                    let caps = KEYWORD.captures(input).unwrap();
+                    let matched_keyword = caps.get(0).unwrap().as_str();
+                    input = &input[matched_keyword.len()..];
+                    let symbol = stream.interner.intern(matched_keyword);
+                    stream.push(Token::keyword(symbol), span);
+                    continue;
+                }
+                let caps = IDENTIFIER.captures(input).unwrap();
+                let matched_identifier = caps.get(0).unwrap().as_str();
+                input = &input[matched_identifier.len()..];
+                stream.push_identifier(matched_identifier, max_identifier_len, span);
+                continue;
+            } else {
+                stream.push_unexpected(input, span)
+            }
+        }
+
+        input = &input[char.len_utf8()..];
+    }
+}
+
+// Line and column of byte offsets into a text, for offsets that only grow, so
+// the text is scanned once however many tokens it has.
+struct Position<'a> {
+    text: &'a str,
+    span: Span,
+}
+
+impl<'a> Position<'a> {
+    fn new(text: &'a str) -> Self {
+        Position {
+            text,
+            span: Span {
+                file: Symbol::EMPTY,
+                line: 1,
+                col: 1,
+                offset: 0,
+            },
+        }
+    }
+
+    // Continue at `offset` as line `line` of `file`.
+    fn jump(&mut self, offset: usize, line: u32, file: Symbol) {
+        self.span = Span {
+            file,
+            line,
+            col: 1,
+            offset,
+        };
+    }
+
+    fn at(&mut self, offset: usize) -> Span {
+        for c in self.text[self.span.offset..offset].chars() {
+            if c == '\n' {
+                self.span.line += 1;
+                self.span.col = 1;
+            } else {
+                self.span.col += 1;
+            }
+        }
+        self.span.offset = offset;
+        self.span
+    }
+}
+
+// Tokens of `source` from `byte_offset` on, for re-lexing the rest of a file
+// after an edit. Their text is interned in `interner`, which should be the
+// one of the tokens they replace. The lexer carries no state from one token
+// to the next, so when the offset is the start of a token (or whitespace)
+// the result is the matching suffix of lexing the whole source, as long as
+// the source has no line markers. An offset inside a character starts at
+// that character, and one past the end gives no tokens.
+pub fn relex_from(source: &str, byte_offset: usize, interner: &mut Interner) -> Vec<Token> {
+    if byte_offset > source.len() {
+        return vec![];
+    }
+    let byte_offset = (0..=byte_offset)
+        .rev()
+        .find(|&offset| source.is_char_boundary(offset))
+        .unwrap_or(0);
+    let mut stream = TokenStream {
+        interner: std::mem::take(interner),
+        ..TokenStream::default()
+    };
+    lex_into(&source[byte_offset..], &mut stream, MAX_IDENTIFIER_LEN);
+    *interner = stream.interner;
+    // Spans count from the start of the suffix, move them to where the
+    // suffix is in the whole source.
+    let start = Position::new(source).at(byte_offset);
+    for token in &mut stream.tokens {
+        if token.span.line == 1 {
+            token.span.col += start.col - 1;
+        }
+        token.span.line += start.line - 1;
+        token.span.offset += byte_offset;
+    }
+    stream.tokens
+}
+
+// The same tokens as `lex_chars`, for input that is all ASCII and so can be
+// scanned a byte at a time without any regex.
+fn lex_ascii(text: &str, stream: &mut TokenStream, max_identifier_len: usize) {
+    let input = text.as_bytes();
+    let is_word = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_';
+    let mut pos = 0;
+    let mut position = Position::new(text);
+    while pos < input.len() {
+        let span = position.at(pos);
+        match input[pos] {
+            b if (b as char).is_whitespace() => {}
+            b'/' if matches!(input.get(pos + 1), Some(b'/' | b'*')) => {
+                pos += stream
+                    .skip_comment(&text[pos..], span)
+                    .expect("starts with a comment");
+                continue;
+            }
+            b'"' => {
+                pos += stream.push_string_literal(&text[pos..], span);
+                continue;
+            }
+            b'#' if span.col == 1 => {
+                match stream.skip_line_marker(&text[pos..], pos, &mut position) {
+                    Some(len) => {
+                        pos += len;
+                        continue;
+                    }
+                    None => stream.push_unexpected(&text[pos..], span),
+                }
+            }
+            b'0'..=b'9' => {
+                let end = pos
+                    + input[pos..]
+                        .iter()
+                        .take_while(|b| b.is_ascii_digit())
+                        .count();
+                // Like `[0-9]+\b`, a constant running into a letter is no
+                // constant at all.
+                if !input.get(end).is_some_and(is_word) {
+                    let symbol = stream.interner.intern(&text[pos..end]);
+                    stream.push(Token::constant(symbol), span);
+                    pos = end;
+                    continue;
+                }
+                stream.push_unexpected(&text[pos..], span);
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                let end = pos + input[pos..].iter().take_while(|b| is_word(b)).count();
+                let word = &text[pos..end];
+                if matches!(word, "void" | "int" | "return" | "__asm__" | "struct") {
+                    let symbol = stream.interner.intern(word);
+                    stream.push(Token::keyword(symbol), span);
+                } else {
+                    stream.push_identifier(word, max_identifier_len, span);
+                }
+                pos = end;
+                continue;
+            }
+            _ => match punctuator(&text[pos..]) {
+                Some(&(punctuator, kind, symbol)) => {
+                    stream.push(Token::new(kind, symbol), span);
+                    pos += punctuator.len();
+                    continue;
+                }
+                None => stream.push_unexpected(&text[pos..], span),
+            },
+        }
+        pos += 1;
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TreeKind {
+    Program,
+    Function,
+    Prototype,
+    GlobalVar,
+    Return,
+    InlineAsm,
+    Unary,
+    // An expression in parentheses, kept so the source can be printed back.
+    Paren,
+    StructDecl,
+    MemberDecl,
+    // `struct` and the tag, in place of a type keyword.
+    StructType,
+    // What's between the parentheses of a function: `void`, or Params
+    // separated by commas.
+    Params,
+    // A type and maybe a name.
+    Param,
+    // Stands in for the type of a declaration that has none, so the other
+    // children keep their positions.
+    ImplicitInt,
+    ErrorTree,
+}
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct NodeId(usize);
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct TokenId(usize);
+#[derive(Debug, PartialEq, Clone)]
+pub struct Node {
+    pub kind: TreeKind,
+    pub children: Vec<Child>,
+}
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Child {
+    Token(TokenId),
+    Tree(NodeId),
+}
+
+// Every node of the tree lives in one arena and points at its children by
+// index, so walking a subtree borrows instead of cloning it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxTree {
+    nodes: Vec<Node>,
+    tokens: Vec<Token>,
+    interner: Interner,
+}
+
+impl SyntaxTree {
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+    pub fn kind(&self, id: NodeId) -> TreeKind {
+        self.nodes[id.0].kind
+    }
+    pub fn children(&self, id: NodeId) -> &[Child] {
+        &self.nodes[id.0].children
+    }
+    pub fn token(&self, id: TokenId) -> &Token {
+        &self.tokens[id.0]
+    }
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+    pub fn text(&self, token: &Token) -> &str {
+        self.interner.resolve(token.text)
+    }
+    // Where `span` is, the way error messages name it.
+    pub fn location(&self, span: Span) -> String {
+        location(&self.interner, span)
+    }
+    pub fn token_child(&self, id: NodeId, index: usize) -> Option<&Token> {
+        match self.children(id).get(index) {
+            Some(Child::Token(t)) => Some(self.token(*t)),
+            _ => None,
+        }
+    }
+    pub fn tree_child(&self, id: NodeId, index: usize) -> Option<NodeId> {
+        match self.children(id).get(index) {
+            Some(Child::Tree(t)) => Some(*t),
+            _ => None,
+        }
+    }
+    /// The statements in the body of a Function, in order.
+    pub fn statements(&self, function: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        // A token missing from the head leaves an ErrorTree in its place, so
+        // the body always starts at the same child.
+        self.children(function)
+            .iter()
+            .skip(FUNCTION_HEAD_LEN)
+            .filter_map(|child| match child {
+                Child::Tree(t) => Some(*t),
+                Child::Token(_) => None,
+            })
+    }
+    /// The token the byte `offset` is in or at either end of. Between two
+    /// tokens that touch, it's the one on the left, like an editor's caret.
+    pub fn token_at_offset(&self, offset: usize) -> Option<&Token> {
+        let first = self
+            .tokens
+            .partition_point(|token| self.end(token) < offset);
+        self.tokens
+            .get(first)
+            .filter(|token| token.span.offset <= offset)
+    }
+    /// The smallest node holding the token at the byte `offset`, or holding
+    /// tokens on both sides of it when it's in whitespace. Outside of every
+    /// item, it's the root.
+    pub fn node_at_offset(&self, offset: usize) -> NodeId {
+        // The token decides between two nodes that touch at `offset`.
+        let (low, high) = match self.token_at_offset(offset) {
+            Some(token) => (token.span.offset, self.end(token)),
+            None => (offset, offset),
+        };
+        let mut node = self.root();
+        'descend: loop {
+            for child in self.children(node) {
+                if let Child::Tree(t) = child {
+                    if self
+                        .byte_range(*t)
+                        .is_some_and(|(start, end)| start <= low && high <= end)
+                    {
+                        node = *t;
+                        continue 'descend;
+                    }
+                }
+            }
+            return node;
+        }
+    }
+    /// `node`, its parent, and so on up to the root.
+    pub fn ancestors(&self, node: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(Some(node), |&node| self.parent(node))
+    }
+    // Nodes are numbered in the order they were opened, so a parent comes
+    // before its children.
+    fn parent(&self, NodeId(node): NodeId) -> Option<NodeId> {
+        (0..node)
+            .rev()
+            .map(NodeId)
+            .find(|&parent| self.children(parent).contains(&Child::Tree(NodeId(node))))
+    }
+    // Where the first token below `id` starts and the last one ends, if it
+    // has any.
+    fn byte_range(&self, id: NodeId) -> Option<(usize, usize)> {
+        Some((self.start(id)?, self.last_end(id)?))
+    }
+    fn start(&self, id: NodeId) -> Option<usize> {
+        self.children(id).iter().find_map(|child| match child {
+            Child::Token(t) => Some(self.token(*t).span.offset),
+            Child::Tree(t) => self.start(*t),
+        })
+    }
+    fn last_end(&self, id: NodeId) -> Option<usize> {
+        self.children(id)
+            .iter()
+            .rev()
+            .find_map(|child| match child {
+                Child::Token(t) => Some(self.end(self.token(*t))),
+                Child::Tree(t) => self.last_end(*t),
+            })
+    }
+    fn end(&self, token: &Token) -> usize {
+        token.span.offset + self.text(token).len()
+    }
+}
+
+// Children of a Function before its body: the type, the name, `(`, the
+// Params, `)` and `{`.
+const FUNCTION_HEAD_LEN: usize = 6;
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Open { kind: TreeKind },
+    Close,
+    Advance,
+}
+struct MarkOpened {
+    index: usize,
+}
+/// The C standard to hold the source to.
+#[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "native", derive(clap::ValueEnum))]
+pub enum Standard {
+    /// ANSI C, which still allows declarations without a type
+    C89,
+    C99,
+    C11,
+    C17,
+}
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    interner: Interner,
+    pos: usize,
+    fuel: Cell<u32>,
+    // How many expressions the one being parsed is nested in.
+    nesting: usize,
+    pub standard: Standard,
+    pub events: Vec<Event>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Parser {
+    pub fn new(tokens: TokenStream) -> Self {
+        let TokenStream {
+            tokens, interner, ..
+        } = tokens;
+        Parser {
+            tokens,
+            interner,
+            pos: 0,
+            fuel: Cell::new(256),
+            nesting: 0,
+            standard: Standard::C17,
+            events: Vec::default(),
+            errors: Vec::default(),
+            warnings: Vec::default(),
+        }
+    }
+
+    fn open(&mut self) -> MarkOpened {
+        let mark = MarkOpened {
+            index: self.events.len(),
+        };
+        self.events.push(Event::Open {
+            kind: TreeKind::ErrorTree,
+        });
+        mark
+    }
+    fn advance(&mut self) {
+        assert!(!self.eof());
+        self.fuel.set(256);
+        self.events.push(Event::Advance);
+        self.pos += 1;
+    }
+
+    fn eof(&self) -> bool {
+        self.pos == self.tokens.len()
+    }
+    fn close(&mut self, m: MarkOpened, kind: TreeKind) {
+        self.events[m.index] = Event::Open { kind };
+        self.events.push(Event::Close);
+    }
+
+    fn nth(&self, lookahead: usize) -> TokenKind {
+        if self.fuel.get() == 0 {
+            panic!("parser is stuck")
+        }
+        self.fuel.set(self.fuel.get() - 1);
+        self.tokens
+            .get(self.pos + lookahead)
+            .map_or(TokenKind::Eof, |t| t.kind)
+    }
+
+    fn at(&self, kind: TokenKind) -> bool {
+        self.nth(0) == kind
+    }
+
+    fn eat(&mut self, kind: TokenKind) -> bool {
+        if self.at(kind) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    // A missing token leaves an empty ErrorTree behind, which shows where it
+    // was missing and keeps the children after it in place.
+    fn expect(&mut self, kind: TokenKind) {
+        if self.eat(kind) {
+            return;
+        }
+        self.errors
+            .push(format!("{}: expected {kind:?}", self.location()));
+        let m = self.open();
+        self.close(m, TreeKind::ErrorTree);
+    }
+
+    fn advance_with_error(&mut self, error: &str) {
+        let m = self.open();
+        self.errors.push(format!("{}: {error}", self.location()));
+        self.advance();
+        self.close(m, TreeKind::ErrorTree);
+    }
+
+    // Where the next token starts, for error messages.
+    fn location(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some(token) => location(&self.interner, token.span),
+            None => match self.tokens.last() {
+                Some(token) if token.span.file != Symbol::EMPTY => {
+                    format!("{}: end of input", self.interner.resolve(token.span.file))
+                }
+                _ => "end of input".into(),
+            },
+        }
+    }
+
+    pub fn build_tree(self) -> SyntaxTree {
+        let mut events = self.events;
+        let mut stack = Vec::new();
+        let mut next_token = 0;
+
+        assert!(matches!(events.pop(), Some(Event::Close)));
+
+        // Count the direct children of every node first, so each child list
+        // is allocated once at its final size.
+        let mut child_counts: Vec<usize> = Vec::new();
+        let mut open = Vec::new();
+        for event in &events {
+            match event {
+                Event::Open { .. } => {
+                    if let Some(parent) = open.last() {
+                        child_counts[*parent] += 1;
+                    }
+                    open.push(child_counts.len());
+                    child_counts.push(0);
+                }
+                Event::Close => {
+                    open.pop().unwrap();
+                }
+                Event::Advance => child_counts[*open.last().unwrap()] += 1,
+            }
+        }
+
+        let mut nodes = Vec::with_capacity(child_counts.len());
+        for event in events {
+            match event {
+                Event::Open { kind } => {
+                    let id = NodeId(nodes.len());
+                    nodes.push(Node {
+                        kind,
+                        children: Vec::with_capacity(child_counts[id.0]),
+                    });
+                    if let Some(parent) = stack.last() {
+                        let NodeId(parent) = *parent;
+                        nodes[parent].children.push(Child::Tree(id));
+                    }
+                    stack.push(id);
+                }
+                Event::Close => {
+                    stack.pop().unwrap();
+                }
+                Event::Advance => {
+                    let NodeId(parent) = *stack.last().unwrap();
+                    nodes[parent]
+                        .children
+                        .push(Child::Token(TokenId(next_token)));
+                    next_token += 1;
+                }
+            }
+        }
+
+        assert!(stack.len() == 1);
+        assert!(next_token == self.tokens.len());
+
+        SyntaxTree {
+            nodes,
+            tokens: self.tokens,
+            interner: self.interner,
+        }
+    }
+
+    pub fn pretty_print(
+        tree: &SyntaxTree,
+        id: NodeId,
+        depth: usize,
+        show_kind: bool,
+        output: &mut String,
+    ) {
+        //  Program(
+        //      Function(
+        //          name = "main"
+        //          type = "int"
+        //          params = "void"
+        //          body = Return(
+        //              Constant(2)
+        //          )
+        //      )
+        //  )
+        if show_kind {
+            writeln!(output, "{:depth$}{:?}(", "", tree.kind(id)).unwrap();
+        }
+        match tree.kind(id) {
+            TreeKind::Program => {
+                for child in tree.children(id) {
+                    if let Child::Tree(t) = child {
+                        Parser::pretty_print(tree, *t, depth + 4, true, output);
+                    }
+                }
+            }
+            TreeKind::Function => {
+                if let Some(
+                    token @ Token {
+                        kind: TokenKind::Identifier,
+                        ..
+                    },
+                ) = tree.token_child(id, 1)
+                {
+                    let text = tree.text(token);
+                    writeln!(output, "{:depth$}name = \"{text}\"", "", depth = depth + 4).unwrap();
+                }
+                Parser::pretty_print_signature(tree, id, depth, output);
+                let head = &tree.children(id)[..FUNCTION_HEAD_LEN.min(tree.children(id).len())];
+                Parser::pretty_print_errors(tree, head, depth, output);
+                for body in tree.statements(id) {
+                    let kind = tree.kind(body);
+                    writeln!(output, "{:depth$}body = {kind:?}(", "", depth = depth + 4).unwrap();
+                    Parser::pretty_print(tree, body, depth + 4, false, output);
+                    writeln!(output, "{:depth$})", "", depth = depth + 4).unwrap();
+                }
+            }
+            TreeKind::Prototype => {
+                if let Some(token) = tree.token_child(id, 1) {
+                    let text = tree.text(token);
+                    writeln!(output, "{:depth$}name = \"{text}\"", "", depth = depth + 4).unwrap();
+                }
+                Parser::pretty_print_signature(tree, id, depth, output);
+            }
+            TreeKind::GlobalVar | TreeKind::MemberDecl | TreeKind::Param => {
+                if let Some(token) = tree.token_child(id, 1) {
+                    let text = tree.text(token);
+                    writeln!(output, "{:depth$}name = \"{text}\"", "", depth = depth + 4).unwrap();
+                }
+                Parser::pretty_print_type(tree, id, depth, output);
+                if let Some(token) = tree.token_child(id, 3) {
+                    let (kind, text) = (token.kind, tree.text(token));
+                    writeln!(
+                        output,
+                        "{:depth$}init = {kind:?}({text})",
+                        "",
+                        depth = depth + 4
+                    )
+                    .unwrap();
+                }
+            }
+            TreeKind::StructDecl => {
+                if let Some(token) = tree.token_child(id, 1) {
+                    let text = tree.text(token);
+                    writeln!(output, "{:depth$}name = \"{text}\"", "", depth = depth + 4).unwrap();
+                }
+                for child in tree.children(id) {
+                    if let Child::Tree(member) = child {
+                        let kind = tree.kind(*member);
+                        writeln!(output, "{:depth$}member = {kind:?}(", "", depth = depth + 4)
+                            .unwrap();
+                        Parser::pretty_print(tree, *member, depth + 4, false, output);
+                        writeln!(output, "{:depth$})", "", depth = depth + 4).unwrap();
+                    }
+                }
+            }
+            TreeKind::Params => {
+                for child in tree.children(id) {
+                    match child {
+                        Child::Token(token) => {
+                            let text = tree.text(tree.token(*token));
+                            if text == "void" {
+                                writeln!(
+                                    output,
+                                    "{:depth$}params = \"void\"",
+                                    "",
+                                    depth = depth + 4
+                                )
+                                .unwrap();
+                            }
+                        }
+                        Child::Tree(param) if tree.kind(*param) == TreeKind::Param => {
+                            writeln!(output, "{:depth$}param = Param(", "", depth = depth + 4)
+                                .unwrap();
+                            Parser::pretty_print(tree, *param, depth + 4, false, output);
+                            writeln!(output, "{:depth$})", "", depth = depth + 4).unwrap();
+                        }
+                        Child::Tree(_) => {}
+                    }
+                }
+            }
+            TreeKind::InlineAsm => {
+                if let Some(token) = tree.token_child(id, 2) {
+                    let (kind, text) = (token.kind, tree.text(token));
+                    writeln!(output, "{:depth$}{kind:?}({text})", "", depth = depth + 4).unwrap();
+                }
+            }
+            TreeKind::Return | TreeKind::Unary | TreeKind::Paren => {
+                if tree.kind(id) == TreeKind::Unary {
+                    if let Some(token) = tree.token_child(id, 0) {
+                        let text = tree.text(token);
+                        writeln!(output, "{:depth$}op = \"{text}\"", "", depth = depth + 4)
+                            .unwrap();
+                    }
+                }
+                match tree.children(id).get(1) {
+                    Some(Child::Token(token)) if tree.token(*token).kind == TokenKind::Constant => {
+                        let token = tree.token(*token);
+                        let (kind, text) = (token.kind, tree.text(token));
+                        writeln!(output, "{:depth$}{kind:?}({text})", "", depth = depth + 4)
+                            .unwrap();
+                    }
+                    Some(Child::Tree(exp)) => {
+                        Parser::pretty_print(tree, *exp, depth + 4, true, output);
+                    }
+                    _ => {}
+                }
+            }
+            // The tokens the parser gave up on, so a partial tree shows
+            // where it went wrong.
+            TreeKind::ErrorTree => {
+                for child in tree.children(id) {
+                    if let Child::Token(token) = child {
+                        let token = tree.token(*token);
+                        let (kind, text) = (token.kind, tree.text(token));
+                        writeln!(output, "{:depth$}{kind:?}({text})", "", depth = depth + 4)
+                            .unwrap();
+                    }
+                }
+            }
+            TreeKind::ImplicitInt | TreeKind::StructType => {}
+        }
+        // The ErrorTrees the arms above don't show, like those of missing
+        // tokens.
+        if !matches!(
+            tree.kind(id),
+            TreeKind::Program | TreeKind::Function | TreeKind::StructDecl | TreeKind::ErrorTree
+        ) {
+            Parser::pretty_print_errors(tree, tree.children(id), depth, output);
+        }
+        if show_kind {
+            writeln!(output, "{:depth$})", "").unwrap();
+        }
+    }
+
+    // The type of a declaration, as written. An implicit int has no line.
+    fn pretty_print_type(tree: &SyntaxTree, id: NodeId, depth: usize, output: &mut String) {
+        if let Some(ty) = declared_type(tree, id) {
+            writeln!(output, "{:depth$}type = \"{ty}\"", "", depth = depth + 4).unwrap();
+        }
+    }
+
+    // The return type and the parameters of a Function or Prototype.
+    fn pretty_print_signature(tree: &SyntaxTree, id: NodeId, depth: usize, output: &mut String) {
+        Parser::pretty_print_type(tree, id, depth, output);
+        if let Some(params) = tree.tree_child(id, 3) {
+            Parser::pretty_print(tree, params, depth, false, output);
+        }
+    }
+
+    // Print the ErrorTrees among `children`.
+    fn pretty_print_errors(
+        tree: &SyntaxTree,
+        children: &[Child],
+        depth: usize,
+        output: &mut String,
+    ) {
+        for child in children {
+            if let Child::Tree(t) = child {
+                if tree.kind(*t) == TreeKind::ErrorTree {
+                    Parser::pretty_print(tree, *t, depth + 4, true, output);
+                }
+            }
+        }
+    }
+}
+
+// digraph {
+//     n0 [shape=box, label="Program"];
+//     n1 [shape=box, label="Function"];
+//     n0 -> n1 [label="0"];
+//     n2 [shape=ellipse, label="Keyword \"int\""];
+//     n1 -> n2 [label="0"];
+//     ...
+// }
+pub fn tree_to_dot(tree: &SyntaxTree) -> String {
+    fn node(tree: &SyntaxTree, node_id: NodeId, next_id: &mut usize, output: &mut String) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        output.push_str(&format!(
+            "    n{id} [shape=box, label=\"{:?}\"];\n",
+            tree.kind(node_id)
+        ));
+        for (index, child) in tree.children(node_id).iter().enumerate() {
+            let child_id = match child {
+                Child::Tree(t) => node(tree, *t, next_id, output),
+                Child::Token(t) => {
+                    let token = tree.token(*t);
+                    let (kind, text) = (token.kind, tree.text(token));
+                    let child_id = *next_id;
+                    *next_id += 1;
+                    output.push_str(&format!(
+                        "    n{child_id} [shape=ellipse, label=\"{kind:?} \\\"{}\\\"\"];\n",
+                        dot_escape(text)
+                    ));
+                    child_id
+                }
+            };
+            output.push_str(&format!("    n{id} -> n{child_id} [label=\"{index}\"];\n"));
+        }
+        id
+    }
+
+    let mut output = String::from("digraph {\n    ordering=out;\n");
+    node(tree, tree.root(), &mut 0, &mut output);
+    output.push_str("}\n");
+    output
+}
+
+// Token text ends up inside a quoted DOT label, where quotes and backslashes
+// must be escaped and braces would otherwise be read as record fields.
+fn dot_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' | '\\' | '{' | '}' | '<' | '>' | '|' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn parse_program(p: &mut Parser) {
+    let m = p.open();
+
+    while !p.eof() {
+        if p.at(TokenKind::Keyword)
+            && p.tokens[p.pos].text == Symbol::STRUCT
+            && matches!(p.nth(2), TokenKind::OpenBrace | TokenKind::Semicolon)
+        {
+            parse_struct_decl(p)
+        } else if p.at(TokenKind::Keyword) && p.nth(2) == TokenKind::OpenParen
+            || p.at(TokenKind::Identifier) && p.nth(1) == TokenKind::OpenParen
+        {
+            parse_function(p)
+        } else if p.at(TokenKind::Keyword)
+            || p.at(TokenKind::Identifier)
+                && matches!(p.nth(1), TokenKind::Equals | TokenKind::Semicolon)
+        {
+            parse_global_var(p)
+        } else {
+            p.advance_with_error("expected a keyword");
+        }
+    }
+    p.close(m, TreeKind::Program);
+    //    Tree {
+    //        kind: TreeKind::Program,
+    //        children: vec![Child::Tree(Tree {
+    //            kind: TreeKind::Function,
+    //            children: vec![Child::Tree(Tree {
+    //                kind: TreeKind::Return,
+    //                children: vec![Child::Token(Token::Constant(2))],
+    //            })],
+    //        })],
+    //    }
+}
+
+// type_specifier = [ "int" | "void" | "struct" <identifier> ]
+//
+// A missing type means int before C99. Later standards dropped that rule, but
+// the declaration is parsed the same way so the rest of it is still checked.
+fn parse_type_specifier(p: &mut Parser) {
+    if p.at(TokenKind::Keyword) && p.tokens[p.pos].text == Symbol::STRUCT {
+        let m = p.open();
+        p.advance();
+        p.expect(TokenKind::Identifier);
+        p.close(m, TreeKind::StructType);
+        return;
+    }
+    if p.at(TokenKind::Keyword) {
+        if matches!(p.tokens[p.pos].text, Symbol::INT | Symbol::VOID) {
+            p.advance();
+        } else {
+            p.advance_with_error("expected a type specifier");
+        }
+        return;
+    }
+    let m = p.open();
+    let message = format!("{}: type specifier missing, defaults to int", p.location());
+    if p.standard == Standard::C89 {
+        p.warnings.push(Warning {
+            code: diagnostics::IMPLICIT_INT,
+            message,
+        });
+    } else {
+        p.errors.push(message);
+    }
+    p.close(m, TreeKind::ImplicitInt);
+}
+
+// global_var = <type_specifier> <identifier> [ "=" <int> ] ";"
+fn parse_global_var(p: &mut Parser) {
+    let m = p.open();
+
+    parse_type_specifier(p);
+    p.expect(TokenKind::Identifier);
+    if p.eat(TokenKind::Equals) {
+        p.expect(TokenKind::Constant);
+    }
+    p.expect(TokenKind::Semicolon);
+
+    p.close(m, TreeKind::GlobalVar);
+}
+
+// struct_decl = "struct" <identifier> [ "{" <member_decl> { <member_decl> } "}" ] ";"
+//
+// Without the braces, it's a forward declaration.
+fn parse_struct_decl(p: &mut Parser) {
+    let m = p.open();
+
+    p.expect(TokenKind::Keyword);
+    p.expect(TokenKind::Identifier);
+    if p.eat(TokenKind::OpenBrace) {
+        // C has no empty structs.
+        if p.at(TokenKind::CloseBrace) {
+            p.errors
+                .push(format!("{}: expected a member declaration", p.location()));
+        }
+        while !p.at(TokenKind::CloseBrace) && !p.eof() {
+            if p.at(TokenKind::Keyword) {
+                parse_member_decl(p);
+            } else {
+                p.advance_with_error("expected a member declaration");
+            }
+        }
+        p.expect(TokenKind::CloseBrace);
+    }
+    p.expect(TokenKind::Semicolon);
+
+    p.close(m, TreeKind::StructDecl);
+}
+
+// member_decl = <type_specifier> <identifier> ";"
+fn parse_member_decl(p: &mut Parser) {
+    let m = p.open();
+
+    parse_type_specifier(p);
+    p.expect(TokenKind::Identifier);
+    p.expect(TokenKind::Semicolon);
+
+    p.close(m, TreeKind::MemberDecl);
+}
+
+// The type a declaration starts with, as written: `int`, `void` or
+// `struct tag`. None for an implicit int or a malformed one.
+fn declared_type(tree: &SyntaxTree, id: NodeId) -> Option<String> {
+    match tree.children(id).first() {
+        Some(Child::Token(token)) if tree.token(*token).kind == TokenKind::Keyword => {
+            Some(tree.text(tree.token(*token)).to_string())
+        }
+        Some(Child::Tree(ty)) if tree.kind(*ty) == TreeKind::StructType => tree
+            .token_child(*ty, 1)
+            .map(|tag| format!("struct {}", tree.text(tag))),
+        _ => None,
+    }
+}
+
+// function = <type_specifier> <identifier> "(" <params> ")" ( ";" | "{" { <statement> } "}" )
+//
+// Without a body, it's a Prototype.
+fn parse_function(p: &mut Parser) {
+    let m = p.open();
+
+    parse_type_specifier(p);
+    p.expect(TokenKind::Identifier);
+    p.expect(TokenKind::OpenParen);
+    let unnamed = parse_params(p);
+    p.expect(TokenKind::CloseParen);
+    if p.eat(TokenKind::Semicolon) {
+        p.close(m, TreeKind::Prototype);
+        return;
+    }
+    // Only a declaration can leave its parameters unnamed.
+    for at in unnamed {
+        p.errors.push(format!("{at}: parameter name omitted"));
+    }
+    p.expect(TokenKind::OpenBrace);
+    while !p.at(TokenKind::CloseBrace) && !p.eof() {
+        if p.at(TokenKind::Keyword) {
+            parse_statement(p);
+        } else {
+            p.advance_with_error("expected a statement");
+        }
+    }
+    p.expect(TokenKind::CloseBrace);
+
+    p.close(m, TreeKind::Function);
+}
+
+// params = "void" | <param> { "," <param> }
+// param = <type_specifier> [ <identifier> ]
+//
+// Gives where each parameter without a name starts.
+fn parse_params(p: &mut Parser) -> Vec<String> {
+    let m = p.open();
+    let mut unnamed = vec![];
+    let at_void = |p: &Parser| p.at(TokenKind::Keyword) && p.tokens[p.pos].text == Symbol::VOID;
+    if at_void(p) && p.nth(1) == TokenKind::CloseParen {
+        p.advance();
+    } else if p.at(TokenKind::CloseParen) {
+        p.errors
+            .push(format!("{}: expected a parameter list", p.location()));
+    } else {
+        loop {
+            let at = p.location();
+            // After a trailing comma.
+            if p.at(TokenKind::CloseParen) {
+                p.errors.push(format!("{at}: expected a type specifier"));
+                break;
+            }
+            let is_void = at_void(p);
+            let param = p.open();
+            let errors = p.errors.len();
+            parse_type_specifier(p);
+            // A missing type is error enough.
+            if !p.eat(TokenKind::Identifier) && p.errors.len() == errors {
+                unnamed.push(at.clone());
+            }
+            if is_void {
+                p.errors
+                    .push(format!("{at}: void has to be the only parameter"));
+            }
+            p.close(param, TreeKind::Param);
+            if !p.eat(TokenKind::Comma) {
+                break;
+            }
+        }
+    }
+    p.close(m, TreeKind::Params);
+    unnamed
+}
+
+// statement = "return" [ <exp> ] ";" | <inline_asm>
+fn parse_statement(p: &mut Parser) {
+    match p.tokens[p.pos].text {
+        Symbol::ASM => return parse_inline_asm(p),
+        Symbol::RETURN => {}
+        _ => {
+            p.advance_with_error("expected a statement");
+            return;
+        }
+    }
+    let m = p.open();
+    p.expect(TokenKind::Keyword);
+    if !p.at(TokenKind::Semicolon) {
+        parse_exp(p);
+    }
+    p.expect(TokenKind::Semicolon);
+
+    p.close(m, TreeKind::Return);
+}
+
+// exp = <int> | <unop> <exp> | "(" <exp> ")"
+// unop = "-" | "~" | "!"
+fn parse_exp(p: &mut Parser) {
+    if p.nesting == MAX_NESTING {
+        // Give up on the rest of the statement rather than on each token.
+        let m = p.open();
+        p.errors
+            .push(format!("{}: expression nested too deeply", p.location()));
+        while !p.eof() && !matches!(p.nth(0), TokenKind::Semicolon | TokenKind::CloseBrace) {
+            p.advance();
+        }
+        p.close(m, TreeKind::ErrorTree);
+        return;
+    }
+    p.nesting += 1;
+    match p.nth(0) {
+        TokenKind::Minus | TokenKind::Tilde | TokenKind::Bang => {
+            let m = p.open();
+            p.advance();
+            parse_exp(p);
+            p.close(m, TreeKind::Unary);
+        }
+        TokenKind::OpenParen => {
+            let m = p.open();
+            p.advance();
+            parse_exp(p);
+            p.expect(TokenKind::CloseParen);
+            p.close(m, TreeKind::Paren);
+        }
+        _ => p.expect(TokenKind::Constant),
+    }
+    p.nesting -= 1;
+}
+
+// inline_asm = "__asm__" "(" <string> ")" ";"
+fn parse_inline_asm(p: &mut Parser) {
+    let m = p.open();
+    p.expect(TokenKind::Keyword);
+    p.expect(TokenKind::OpenParen);
+    p.expect(TokenKind::StringLiteral);
+    p.expect(TokenKind::CloseParen);
+    p.expect(TokenKind::Semicolon);
+
+    p.close(m, TreeKind::InlineAsm);
+}
+
+// The exit status of a process is the low 8 bits of what main returns, so a
+// constant outside 0-255 from main doesn't reach the shell as written.
+pub fn exit_code_warnings(tree: &SyntaxTree) -> Vec<Warning> {
+    let mut warnings = vec![];
+    for child in tree.children(tree.root()) {
+        let Child::Tree(function) = child else {
+            continue;
+        };
+        let is_main = tree.kind(*function) == TreeKind::Function
+            && tree
+                .token_child(*function, 1)
+                .is_some_and(|name| tree.text(name) == "main");
+        if !is_main {
+            continue;
+        }
+        let values = tree
+            .statements(*function)
+            .filter(|statement| tree.kind(*statement) == TreeKind::Return)
+            .filter_map(|statement| tree.children(statement).get(1))
+            .filter_map(|exp| fold_constant(tree, exp));
+        for value in values.filter(|value| !(0..=255).contains(value)) {
+            warnings.push(Warning {
+                code: diagnostics::EXIT_CODE_RANGE,
+                message: format!(
+                    "main returns {value}, which the exit status truncates to {}",
+                    value & 0xff
+                ),
+            });
+        }
+    }
+    warnings
+}
+
+// The value of an expression made of constants and unary operators, with
+// int's wrapping, or None for anything else.
+fn fold_constant(tree: &SyntaxTree, exp: &Child) -> Option<i64> {
+    match strip_parens(tree, exp) {
+        Child::Token(token) => {
+            let token = tree.token(*token);
+            (token.kind == TokenKind::Constant)
+                .then(|| constant_value(tree, token, false).ok())
+                .flatten()
+        }
+        Child::Tree(unary) if tree.kind(*unary) == TreeKind::Unary => {
+            let operand = tree.children(*unary).get(1)?;
+            let value = match tree.token_child(*unary, 0)?.kind {
+                TokenKind::Minus => match strip_parens(tree, operand) {
+                    Child::Token(token) => constant_value(tree, tree.token(*token), true).ok()?,
+                    operand => (fold_constant(tree, operand)? as i32).wrapping_neg().into(),
+                },
+                TokenKind::Tilde => !fold_constant(tree, operand)?,
+                TokenKind::Bang => (fold_constant(tree, operand)? == 0).into(),
+                _ => return None,
+            };
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+// Statements after a return can never run.
+pub fn unreachable_code_warnings(tree: &SyntaxTree) -> Vec<Warning> {
+    let mut warnings = vec![];
+    for child in tree.children(tree.root()) {
+        let Child::Tree(function) = child else {
+            continue;
+        };
+        if tree.kind(*function) != TreeKind::Function {
+            continue;
+        }
+        let mut statements = tree.statements(*function);
+        if !statements.any(|statement| tree.kind(statement) == TreeKind::Return) {
+            continue;
+        }
+        if let Some(unreachable) = statements.next() {
+            let name = tree
+                .token_child(*function, 1)
+                .map_or("", |name| tree.text(name));
+            let at = tree.token_child(unreachable, 0).map(|token| token.span);
+            warnings.push(Warning {
+                code: diagnostics::UNREACHABLE_CODE,
+                message: format!(
+                    "{}: code after return in {name} will never be executed",
+                    tree.location(at.unwrap_or_default())
+                ),
+            });
+        }
+    }
+    warnings
+}
+
+// Codegen returns 0 from an int function that falls off its end, which the
+// standard only promises for main.
+pub fn missing_return_warnings(tree: &SyntaxTree) -> Vec<Warning> {
+    let mut warnings = vec![];
+    for child in tree.children(tree.root()) {
+        let Child::Tree(function) = child else {
+            continue;
+        };
+        if tree.kind(*function) != TreeKind::Function {
+            continue;
+        }
+        let returns_void = tree
+            .token_child(*function, 0)
+            .is_some_and(|token| token.text == Symbol::VOID);
+        let name = tree
+            .token_child(*function, 1)
+            .map_or("", |name| tree.text(name));
+        let last = tree.statements(*function).last();
+        if returns_void || name == "main" || last.is_some_and(|s| tree.kind(s) == TreeKind::Return)
+        {
+            continue;
+        }
+        let close = tree
+            .children(*function)
+            .iter()
+            .rev()
+            .find_map(|child| match child {
+                Child::Token(token) if tree.token(*token).kind == TokenKind::CloseBrace => {
+                    Some(tree.token(*token).span)
+                }
+                _ => None,
+            });
+        warnings.push(Warning {
+            code: diagnostics::MISSING_RETURN,
+            message: format!(
+                "{}: control reaches end of non-void function {name}",
+                tree.location(close.unwrap_or_default())
+            ),
+        });
+    }
+    warnings
+}
+
+// A prototype has to agree with the definition of its function, and with
+// the other prototypes for it.
+pub fn check_prototypes(tree: &SyntaxTree) -> Result<(), String> {
+    // The return type and the type of each parameter, where a missing type is
+    // int. Names don't matter, and `(void)` is no parameters at all.
+    let signature = |id| {
+        let ty = |id| declared_type(tree, id).unwrap_or_else(|| "int".to_string());
+        let params: Vec<String> = tree.tree_child(id, 3).map_or(vec![], |params| {
+            tree.children(params)
+                .iter()
+                .filter_map(|child| match child {
+                    Child::Tree(param) if tree.kind(*param) == TreeKind::Param => Some(ty(*param)),
+                    _ => None,
+                })
+                .collect()
+        });
+        (ty(id), params)
+    };
+    let declarations: Vec<NodeId> = tree
+        .children(tree.root())
+        .iter()
+        .filter_map(|child| match child {
+            Child::Tree(t) if matches!(tree.kind(*t), TreeKind::Function | TreeKind::Prototype) => {
+                Some(*t)
+            }
+            _ => None,
+        })
+        .collect();
+    for (i, first) in declarations.iter().enumerate() {
+        let name = tree.token_child(*first, 1).map(|token| token.text);
+        for second in &declarations[i + 1..] {
+            let Some(token) = tree.token_child(*second, 1) else {
+                continue;
+            };
+            if Some(token.text) == name && signature(*first) != signature(*second) {
+                return Err(format!(
+                    "{}: conflicting types for {}",
+                    tree.location(token.span),
+                    tree.text(token)
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// `return;` only belongs in a void function, and `return x;` only in the
+// others.
+pub fn check_returns(tree: &SyntaxTree) -> Result<(), String> {
+    for child in tree.children(tree.root()) {
+        let Child::Tree(function) = child else {
+            continue;
+        };
+        if tree.kind(*function) != TreeKind::Function {
+            continue;
+        }
+        let returns_void = tree
+            .token_child(*function, 0)
+            .is_some_and(|token| token.text == Symbol::VOID);
+        for statement in tree.statements(*function) {
+            if tree.kind(statement) != TreeKind::Return {
+                continue;
+            }
+            let has_value = !matches!(
+                tree.children(statement).get(1),
+                Some(Child::Token(token)) if tree.token(*token).kind == TokenKind::Semicolon
+            );
+            let at = tree.token_child(statement, 0).map(|token| token.span);
+            let at = tree.location(at.unwrap_or_default());
+            if has_value && returns_void {
+                return Err(format!(
+                    "{at}: 'return' with a value, in function returning void"
+                ));
+            }
+            if !has_value && !returns_void {
+                return Err(format!(
+                    "{at}: 'return' with no value, in function returning non-void"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Every constant has to fit in an int, the only type there is.
+pub fn check_constants(tree: &SyntaxTree) -> Result<(), String> {
+    for (id, node) in tree.nodes.iter().enumerate() {
+        // The constants in parentheses are checked with the expression
+        // around them, which can negate them.
+        if node.kind == TreeKind::Paren {
+            continue;
+        }
+        let negates = node.kind == TreeKind::Unary
+            && tree
+                .token_child(NodeId(id), 0)
+                .is_some_and(|op| op.kind == TokenKind::Minus);
+        for child in &node.children {
+            if let Child::Token(token) = strip_parens(tree, child) {
+                let token = tree.token(*token);
+                if token.kind == TokenKind::Constant {
+                    constant_value(tree, token, negates)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Every constant is an int for now, so anything past i32 is refused instead
+// of being truncated by the assembler. A negated literal is folded first, so
+// `-2147483648` is in range.
+pub(crate) fn constant_value(
+    tree: &SyntaxTree,
+    token: &Token,
+    negated: bool,
+) -> Result<i64, String> {
+    let text = tree.text(token);
+    text.parse::<i64>()
+        .ok()
+        .map(|value| if negated { -value } else { value })
+        .filter(|value| i32::try_from(*value).is_ok())
+        .ok_or_else(|| {
+            let at = tree.location(token.span);
+            format!("{at}: integer constant out of range: {text}")
+        })
+}
+
+// program = Program(function_definition*, static_variable*)
+// function_definition = Function(identifier name, instruction* instructions,
+//                                int stack_size)
+// static_variable = StaticVariable(identifier name, int? init)
+// instruction = Mov(operand src, operand dst) | Unary(unary_operator, operand)
+//             | Binary(binary_operator, operand, operand)
+//             | Cmp(operand, operand) | SetE | Movzb | Ret | InlineAsm(string)
+// unary_operator = Neg | Not
+// binary_operator = Add | Sub | Mult
+// operand = Imm(int) | Register | Stack(int)
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ASMProgram {
+    pub functions: Vec<ASMFunction>,
+    pub statics: Vec<ASMStaticVariable>,
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct ASMStaticVariable {
+    pub identifier: Symbol,
+    pub init: Option<i64>,
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct ASMFunction {
+    pub identifier: Symbol,
+    pub instructions: Vec<ASMInstruction>,
+    // Bytes of stack for the temporaries. A function with none gets no frame.
+    pub stack_size: i64,
+    // Each temporary and its offset from %rbp, in the order they were given.
+    pub stack_slots: Vec<(Symbol, i64)>,
+}
+#[derive(Debug, PartialEq, Clone)]
+pub enum ASMInstruction {
+    Mov { src: ASMOperand, dst: ASMOperand },
+    Unary(ASMUnaryOperator, ASMOperand),
+    Binary(ASMBinaryOperator, ASMOperand, ASMOperand),
+    Cmp { src: ASMOperand, dst: ASMOperand },
+    // Set %al to whether the last comparison found its operands equal.
+    SetE,
+    // Widen %al into %eax.
+    Movzb,
+    Ret,
+    InlineAsm(String),
+    // A source position, emitted as a `.loc` with debug info.
+    Loc(Span),
+}
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ASMUnaryOperator {
+    Neg,
+    Not,
+}
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ASMBinaryOperator {
+    Add,
+    Sub,
+    Mult,
+}
+#[derive(Debug, PartialEq, Clone)]
+pub enum ASMOperand {
+    Imm(i64),
+    Register,
+    // An offset from %rbp.
+    Stack(i64),
+}
+
+// Lower TACKY to assembly. Every temporary gets its own stack slot, and
+// each instruction goes through %eax, so no instruction has two memory
+// operands. Frames are rounded up to a multiple of `stack_align` bytes.
+pub fn generate_assembly(program: &TackyProgram, stack_align: i64) -> ASMProgram {
+    ASMProgram {
+        functions: program
+            .functions
+            .iter()
+            .map(|function| generate_function(function, stack_align))
+            .collect(),
+        statics: program
+            .statics
+            .iter()
+            .map(|variable| ASMStaticVariable {
+                identifier: variable.identifier,
+                init: variable.init,
+            })
+            .collect(),
+    }
+}
+
+// The System V ABI wants %rsp 16-byte aligned at every call, so frames are
+// kept a multiple of this unless the options ask for another alignment.
+pub const STACK_ALIGN: i64 = 16;
+// The biggest stack frame a function gets unless the options say otherwise.
+// A frame this big is more likely a runaway expression than a real need.
+pub const MAX_STACK: i64 = 1 << 20;
+// Bytes of stack per temporary, all of them ints.
+const SLOT_SIZE: i64 = 4;
+
+// The bytes of stack `slots` temporaries take, rounded up to `align`.
+fn frame_size(slots: usize, align: i64) -> i64 {
+    let bytes = SLOT_SIZE * slots as i64;
+    (bytes + align - 1) / align * align
+}
+
+fn generate_function(function: &TackyFunction, stack_align: i64) -> ASMFunction {
+    // Stack slots of the temporaries, in the order they first appear.
+    let mut slots: HashMap<Symbol, i64> = HashMap::new();
+    let mut operand = |val: &TackyVal| -> ASMOperand {
+        match val {
+            TackyVal::Constant(value) => ASMOperand::Imm(*value),
+            TackyVal::Var(name) => {
+                let next = -SLOT_SIZE * (slots.len() as i64 + 1);
+                ASMOperand::Stack(*slots.entry(*name).or_insert(next))
+            }
+        }
+    };
+    let mut instructions = vec![];
+    for instruction in &function.instructions {
+        match instruction {
+            TackyInstruction::Return(value) => {
+                if let Some(value) = value {
+                    instructions.push(ASMInstruction::Mov {
+                        src: operand(value),
+                        dst: ASMOperand::Register,
+                    });
+                }
+                instructions.push(ASMInstruction::Ret);
+            }
+            TackyInstruction::Unary { op, src, dst } => {
+                instructions.push(ASMInstruction::Mov {
+                    src: operand(src),
+                    dst: ASMOperand::Register,
+                });
+                match op {
+                    TackyUnaryOperator::Negate => instructions.push(ASMInstruction::Unary(
+                        ASMUnaryOperator::Neg,
+                        ASMOperand::Register,
+                    )),
+                    TackyUnaryOperator::Complement => instructions.push(ASMInstruction::Unary(
+                        ASMUnaryOperator::Not,
+                        ASMOperand::Register,
+                    )),
+                    // !e is 1 when e is 0 and 0 otherwise.
+                    TackyUnaryOperator::Not => instructions.extend([
+                        ASMInstruction::Cmp {
+                            src: ASMOperand::Imm(0),
+                            dst: ASMOperand::Register,
+                        },
+                        ASMInstruction::Mov {
+                            src: ASMOperand::Imm(0),
+                            dst: ASMOperand::Register,
+                        },
+                        ASMInstruction::SetE,
+                        ASMInstruction::Movzb,
+                    ]),
+                }
+                instructions.push(ASMInstruction::Mov {
+                    src: ASMOperand::Register,
+                    dst: operand(dst),
+                });
+            }
+            TackyInstruction::Binary {
+                op,
+                src1,
+                src2,
+                dst,
+            } => {
+                let op = match op {
+                    TackyBinaryOperator::Add => ASMBinaryOperator::Add,
+                    TackyBinaryOperator::Subtract => ASMBinaryOperator::Sub,
+                    TackyBinaryOperator::Multiply => ASMBinaryOperator::Mult,
+                };
+                instructions.extend([
+                    ASMInstruction::Mov {
+                        src: operand(src1),
+                        dst: ASMOperand::Register,
+                    },
+                    ASMInstruction::Binary(op, operand(src2), ASMOperand::Register),
+                    ASMInstruction::Mov {
+                        src: ASMOperand::Register,
+                        dst: operand(dst),
+                    },
+                ]);
+            }
+            TackyInstruction::InlineAsm(text) => {
+                instructions.push(ASMInstruction::InlineAsm(text.clone()))
+            }
+            TackyInstruction::Loc(span) => instructions.push(ASMInstruction::Loc(*span)),
+        }
+    }
+    let mut stack_slots: Vec<(Symbol, i64)> = slots.into_iter().collect();
+    stack_slots.sort_by_key(|&(_, offset)| -offset);
+    ASMFunction {
+        identifier: function.identifier,
+        instructions,
+        stack_size: frame_size(stack_slots.len(), stack_align),
+        stack_slots,
+    }
+}
+
+// main:
+//     tmp.0 -4(%rbp)
+//     tmp.1 -8(%rbp)
+//
+// Where each function keeps its temporaries, to match the operands of the
+// assembly up with the TACKY.
+pub fn stack_map(asm: &ASMProgram, interner: &Interner) -> String {
+    let mut output = String::new();
+    for function in &asm.functions {
+        writeln!(output, "{}:", interner.resolve(function.identifier)).unwrap();
+        for (name, offset) in &function.stack_slots {
+            let name = interner.resolve(*name);
+            writeln!(output, "    {name} {offset}(%rbp)").unwrap();
+        }
+    }
+    output
+}
+
+// The expression inside any parentheses around `exp`.
+pub(crate) fn strip_parens<'a>(tree: &'a SyntaxTree, exp: &'a Child) -> &'a Child {
+    match exp {
+        Child::Tree(paren) if tree.kind(*paren) == TreeKind::Paren => {
+            let inner = tree
+                .children(*paren)
+                .get(1)
+                .expect("parentheses without an expression");
+            strip_parens(tree, inner)
+        }
+        _ => exp,
+    }
+}
+
+// With `debug_info`, the source positions become `.file` and `.loc`
+// directives, numbering the files in the order they first show up. Code from
+// text without line markers has no file to point at and gets none.
+pub fn emit_program(asm: &ASMProgram, interner: &Interner, debug_info: bool) -> Vec<u8> {
+    let mut output = vec![];
+
+    let ASMProgram { functions, statics } = asm;
+    let mut files: Vec<Symbol> = vec![];
+    if debug_info {
+        let locs = functions
+            .iter()
+            .flat_map(|function| &function.instructions)
+            .filter_map(|instruction| match instruction {
+                ASMInstruction::Loc(span) if span.file != Symbol::EMPTY => Some(span.file),
+                _ => None,
+            });
+        for file in locs {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+        for (number, file) in files.iter().enumerate() {
+            let name = interner.resolve(*file).replace('\\', "\\\\");
+            let name = name.replace('"', "\\\"");
+            output.extend_from_slice(format!("\t.file\t{} \"{name}\"\n", number + 1).as_bytes());
+        }
+    }
+    for ASMFunction {
+        identifier,
+        instructions,
+        stack_size,
+        ..
+    } in functions
+    {
+        let identifier = symbol_name(interner.resolve(*identifier));
+        output.extend_from_slice(b"\t.globl\t");
+        output.extend_from_slice(identifier.as_bytes());
+        output.extend_from_slice(b"\n");
+        output.extend_from_slice(identifier.as_bytes());
+        output.extend_from_slice(b":\n");
+        let has_frame = *stack_size > 0;
+        if has_frame {
+            output.extend_from_slice(b"\tpushq\t%rbp\n\tmovq\t%rsp, %rbp\n");
+            output.extend_from_slice(format!("\tsubq\t${stack_size}, %rsp\n").as_bytes());
+        }
+        for instruction in instructions {
+            match instruction {
+                ASMInstruction::Mov { src, dst } => {
+                    output.extend_from_slice(b"\tmovl\t");
+                    emit_op(src, &mut output);
+                    output.extend_from_slice(b", ");
+                    emit_op(dst, &mut output);
+                    output.extend_from_slice(b"\n");
+                }
+                ASMInstruction::Unary(op, operand) => {
+                    output.extend_from_slice(match op {
+                        ASMUnaryOperator::Neg => b"\tnegl\t",
+                        ASMUnaryOperator::Not => b"\tnotl\t",
+                    });
+                    emit_op(operand, &mut output);
+                    output.extend_from_slice(b"\n");
+                }
+                ASMInstruction::Binary(op, src, dst) => {
+                    output.extend_from_slice(match op {
+                        ASMBinaryOperator::Add => b"\taddl\t",
+                        ASMBinaryOperator::Sub => b"\tsubl\t",
+                        ASMBinaryOperator::Mult => b"\timull\t",
+                    });
+                    emit_op(src, &mut output);
+                    output.extend_from_slice(b", ");
+                    emit_op(dst, &mut output);
+                    output.extend_from_slice(b"\n");
+                }
+                ASMInstruction::Cmp { src, dst } => {
+                    output.extend_from_slice(b"\tcmpl\t");
+                    emit_op(src, &mut output);
+                    output.extend_from_slice(b", ");
+                    emit_op(dst, &mut output);
+                    output.extend_from_slice(b"\n");
+                }
+                ASMInstruction::SetE => output.extend_from_slice(b"\tsete\t%al\n"),
+                ASMInstruction::Movzb => output.extend_from_slice(b"\tmovzbl\t%al, %eax\n"),
+                ASMInstruction::Ret => {
+                    if has_frame {
+                        output.extend_from_slice(b"\tmovq\t%rbp, %rsp\n\tpopq\t%rbp\n");
+                    }
+                    output.extend_from_slice(b"\tret\n");
+                }
+                ASMInstruction::InlineAsm(text) => {
+                    output.extend_from_slice(b"\t");
+                    output.extend_from_slice(text.as_bytes());
+                    output.extend_from_slice(b"\n");
+                }
+                ASMInstruction::Loc(span) => {
+                    if let Some(number) = files.iter().position(|file| *file == span.file) {
+                        let Span { line, col, .. } = span;
+                        let loc = format!("\t.loc\t{} {line} {col}\n", number + 1);
+                        output.extend_from_slice(loc.as_bytes());
+                    }
+                }
+            }
+        }
+    }
+    for ASMStaticVariable { identifier, init } in statics {
+        let identifier = symbol_name(interner.resolve(*identifier));
+        output.extend_from_slice(b"\t.globl\t");
+        output.extend_from_slice(identifier.as_bytes());
+        output.extend_from_slice(b"\n");
+        // Like gcc, zero-initialized variables take no space in the object file.
+        match init {
+            Some(value) if *value != 0 => output.extend_from_slice(b"\t.data\n"),
+            _ => output.extend_from_slice(b"\t.bss\n"),
+        }
+        output.extend_from_slice(b"\t.balign\t4\n");
+        output.extend_from_slice(identifier.as_bytes());
+        output.extend_from_slice(b":\n");
+        match init {
+            Some(value) if *value != 0 => {
+                output.extend_from_slice(b"\t.long\t");
+                output.extend_from_slice(value.to_string().as_bytes());
+                output.extend_from_slice(b"\n");
+            }
+            _ => output.extend_from_slice(b"\t.zero\t4\n"),
+        }
+    }
+    output
+}
+
+// Mach-O prefixes C symbols with an underscore, ELF uses them as they are.
+fn symbol_name(name: &str) -> String {
+    if cfg!(target_os = "macos") {
+        format!("_{name}")
+    } else {
+        name.into()
+    }
+}
+
+fn emit_op(op: &ASMOperand, output: &mut Vec<u8>) {
+    match op {
+        ASMOperand::Imm(i) => {
+            output.extend_from_slice(b"$");
+            output.extend_from_slice(i.to_string().as_bytes());
+        }
+        ASMOperand::Register => {
+            output.extend_from_slice(b"%eax");
+        }
+        ASMOperand::Stack(offset) => {
+            output.extend_from_slice(offset.to_string().as_bytes());
+            output.extend_from_slice(b"(%rbp)");
+        }
+    }
+}
+
+// Pipeline stages whose result can be watched with a StageObserver, in the
+// order they run.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Stage {
+    Tokens,
+    Cst,
+    Tacky,
+    AsmIr,
+    Assembly,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Tokens,
+        Stage::Cst,
+        Stage::Tacky,
+        Stage::AsmIr,
+        Stage::Assembly,
+    ];
+
+    // The numbering leaves room for the AST, validation and optimized TACKY
+    // stages.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Stage::Tokens => "01-tokens.txt",
+            Stage::Cst => "02-cst.txt",
+            Stage::Tacky => "05-tacky.txt",
+            Stage::AsmIr => "07-asm-ir.txt",
+            Stage::Assembly => "08-final.s",
+        }
+    }
+}
+
+/// Receives the printed result of each stage as the pipeline runs.
+pub trait StageObserver {
+    fn observe(&mut self, stage: Stage, output: &str);
+    /// The tokens, before any lex errors stop the compilation.
+    fn tokens(&mut self, _tokens: &TokenStream) {}
+    /// The syntax tree, once the passes have accepted it.
+    fn tree(&mut self, _tree: &SyntaxTree) {}
+    /// The assembly IR, with every temporary in its stack slot.
+    fn asm(&mut self, _asm: &ASMProgram, _interner: &Interner) {}
+    /// A warning that isn't promoted to an error, as soon as it's found.
+    fn warning(&mut self, _warning: &Warning) {}
+}
+
+impl StageObserver for Vec<(Stage, String)> {
+    fn observe(&mut self, stage: Stage, output: &str) {
+        self.push((stage, output.into()));
+    }
+}
+
+/// Every way a compilation can fail. Each variant holds the diagnostics of
+/// the stage that failed.
+#[derive(Debug)]
+pub enum CompileError {
+    /// A file couldn't be read or written
+    Io(PathBuf, io::Error),
+    Lex(Vec<String>),
+    Parse(Vec<String>),
+    /// The program parsed, but breaks a rule of the language, like a
+    /// constant that doesn't fit its type
+    Semantic(Vec<String>),
+    /// The program is valid, but zcc can't compile it yet
+    Codegen(String),
+    /// gcc or another external tool couldn't be run or failed
+    Toolchain(String),
+    /// A stage panicked, which is a bug in zcc
+    Internal(String),
+    /// The options ask for something the program doesn't have, like a
+    /// function it doesn't define
+    Usage(String),
+}
+
+impl CompileError {
+    /// The diagnostics one by one, for printing each on its own line.
+    pub fn messages(&self) -> Vec<String> {
+        match self {
+            CompileError::Lex(errors)
+            | CompileError::Parse(errors)
+            | CompileError::Semantic(errors) => errors.clone(),
+            error => vec![error.to_string()],
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::Io(path, error) => write!(f, "{}: {error}", path.display()),
+            CompileError::Lex(errors)
+            | CompileError::Parse(errors)
+            | CompileError::Semantic(errors) => write!(f, "{}", errors.join("\n")),
+            CompileError::Codegen(error)
+            | CompileError::Toolchain(error)
+            | CompileError::Internal(error)
+            | CompileError::Usage(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CompileError::Io(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for compiling through the library, matching the driver's flags.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompileOptions {
+    pub standard: Standard,
+    pub max_identifier_len: usize,
+    /// Stop once this stage has run
+    pub last_stage: Stage,
+    /// Run the checks a program has to pass before code generation
+    pub checks: bool,
+    /// Warn when main returns a constant the exit status can't hold
+    pub warn_exit_code: bool,
+    /// Report warnings with these codes as errors
+    pub werror_codes: Vec<String>,
+    /// On parse errors, still show the observer the partial tree
+    pub recover: bool,
+    /// Only generate code for the function with this name, plus the globals
+    pub only: Option<String>,
+    /// Name the compiler and its version in the assembly
+    pub emit_ident: bool,
+    /// Round stack frames up to a multiple of this many bytes, a power of
+    /// two
+    pub stack_align: i64,
+    /// Refuse to generate a stack frame bigger than this many bytes
+    pub max_stack: i64,
+    /// Emit `.file` and `.loc` directives, so a debugger can map the code
+    /// back to source lines
+    pub debug_info: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            standard: Standard::C17,
+            max_identifier_len: MAX_IDENTIFIER_LEN,
+            last_stage: Stage::Assembly,
+            checks: true,
+            warn_exit_code: false,
+            werror_codes: vec![],
+            recover: false,
+            only: None,
+            emit_ident: false,
+            stack_align: STACK_ALIGN,
+            max_stack: MAX_STACK,
+            debug_info: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CompileOutput {
+    // Empty when `last_stage` stops before the assembly is emitted.
+    pub assembly: String,
+    pub warnings: Vec<Warning>,
+}
+
+// The whole pipeline on in-memory text, for embedders that have no files or
+// gcc around. Errors are the diagnostics of the first stage that failed.
+pub fn compile_to_asm(
+    source: &str,
+    observer: Option<&mut dyn StageObserver>,
+) -> Result<String, CompileError> {
+    compile_source(source, &CompileOptions::default(), observer).map(|output| output.assembly)
+}
+
+// Like `compile_to_asm`, for source that hasn't been checked to be UTF-8 yet.
+pub fn compile_bytes(
+    source: &[u8],
+    options: CompileOptions,
+) -> Result<CompileOutput, CompileError> {
+    let source = std::str::from_utf8(source).map_err(|error| {
+        CompileError::Lex(vec![format!(
+            "invalid UTF-8 at byte {}",
+            error.valid_up_to()
+        )])
+    })?;
+    compile_source(source, &options, None)
+}
+
+// Run `stage`, turning a panic into an Internal error that names it. The
+// panic hook has already printed what went wrong.
+fn run_stage<T>(name: &str, stage: impl FnOnce() -> T) -> Result<T, CompileError> {
+    panic::catch_unwind(AssertUnwindSafe(stage))
+        .map_err(|_| CompileError::Internal(format!("zcc panicked during {name}")))
+}
+
+// Hand `warnings` to the observer and keep them in `kept`, except those whose
+// code is one of `werror_codes`, which are returned as errors.
+fn report_warnings(
+    options: &CompileOptions,
+    warnings: Vec<Warning>,
+    observer: &mut Option<&mut dyn StageObserver>,
+    kept: &mut Vec<Warning>,
+) -> Vec<String> {
+    let mut promoted = vec![];
+    for warning in warnings {
+        if options.werror_codes.iter().any(|code| code == warning.code) {
+            promoted.push(format!("{} [{}]", warning.message, warning.code));
+        } else {
+            if let Some(observer) = observer.as_mut() {
+                observer.warning(&warning);
+            }
+            kept.push(warning);
+        }
+    }
+    promoted
+}
+
+// The pipeline the driver runs too, from source text to assembly. The
+// options pick the passes and where to stop.
+pub fn compile_source(
+    source: &str,
+    options: &CompileOptions,
+    mut observer: Option<&mut dyn StageObserver>,
+) -> Result<CompileOutput, CompileError> {
+    let mut warnings = vec![];
+    let tokens = lex_with_limit(source.into(), options.max_identifier_len);
+    if let Some(observer) = observer.as_mut() {
+        observer.tokens(&tokens);
+        observer.observe(Stage::Tokens, &tokens.to_string());
+    }
+    if tokens.is_err() {
+        return Err(CompileError::Lex(tokens.errors().to_vec()));
+    }
+    if options.last_stage == Stage::Tokens {
+        return Ok(CompileOutput {
+            assembly: String::new(),
+            warnings,
+        });
+    }
+
+    let mut parser = Parser::new(tokens);
+    parser.standard = options.standard;
+    run_stage("parsing", || parse_program(&mut parser))?;
+    // Promoted warnings are semantic errors, wherever they were found, unless
+    // the program didn't even parse.
+    let parser_warnings = std::mem::take(&mut parser.warnings);
+    let promoted = report_warnings(options, parser_warnings, &mut observer, &mut warnings);
+    if !parser.errors.is_empty() {
+        let mut errors = promoted;
+        errors.append(&mut parser.errors);
+        if options.recover {
+            // A tree that can't even be built is left out.
+            if let Ok(tree) = run_stage("building the syntax tree", || parser.build_tree()) {
+                if let Some(observer) = observer.as_mut() {
+                    let mut pretty = String::new();
+                    Parser::pretty_print(&tree, tree.root(), 0, true, &mut pretty);
+                    observer.observe(Stage::Cst, &pretty);
+                }
+            }
+        }
+        return Err(CompileError::Parse(errors));
+    }
+    let mut unit = passes::CompilationUnit {
+        tree: run_stage("building the syntax tree", || parser.build_tree())?,
+        warnings: vec![],
+    };
+    let result = passes::PassRegistry::from_options(options).run(&mut unit);
+    let passes::CompilationUnit {
+        mut tree,
+        warnings: pass_warnings,
+    } = unit;
+    let mut errors = promoted;
+    errors.append(&mut report_warnings(
+        options,
+        pass_warnings,
+        &mut observer,
+        &mut warnings,
+    ));
+    if let Err(error) = result {
+        errors.push(error);
+    }
+    if !errors.is_empty() {
+        return Err(CompileError::Semantic(errors));
+    }
+    if let Some(observer) = observer.as_mut() {
+        let mut pretty = String::new();
+        Parser::pretty_print(&tree, tree.root(), 0, true, &mut pretty);
+        observer.observe(Stage::Cst, &pretty);
+        observer.tree(&tree);
+    }
+    if options.last_stage == Stage::Cst {
+        return Ok(CompileOutput {
+            assembly: String::new(),
+            warnings,
+        });
+    }
+
+    let tacky = run_stage("TACKY generation", || generate_tacky(&mut tree))?;
+    let mut tacky = tacky.map_err(CompileError::Codegen)?;
+    if let Some(name) = &options.only {
+        // Drop the other functions before they're lowered, the globals stay.
+        let interner = tree.interner();
+        tacky
+            .functions
+            .retain(|function| interner.resolve(function.identifier) == name);
+        if tacky.functions.is_empty() {
+            return Err(CompileError::Usage(format!("no function named {name}")));
+        }
+    }
+    if let Some(observer) = observer.as_mut() {
+        observer.observe(Stage::Tacky, &format!("{tacky:#?}\n"));
+    }
+    if options.last_stage == Stage::Tacky {
+        return Ok(CompileOutput {
+            assembly: String::new(),
+            warnings,
+        });
+    }
+
+    let align = options.stack_align;
+    if align <= 0 || align.count_ones() != 1 {
+        let message = format!("stack alignment has to be a power of two, not {align}");
+        return Err(CompileError::Usage(message));
+    }
+    let asm = run_stage("assembly generation", || generate_assembly(&tacky, align))?;
+    for function in &asm.functions {
+        if function.stack_size > options.max_stack {
+            return Err(CompileError::Codegen(format!(
+                "{}: stack frame of {} bytes is over the limit of {}",
+                tree.interner().resolve(function.identifier),
+                function.stack_size,
+                options.max_stack
+            )));
+        }
+    }
+    if let Some(observer) = observer.as_mut() {
+        observer.asm(&asm, tree.interner());
+        observer.observe(Stage::AsmIr, &format!("{asm:#?}\n"));
+    }
+    if options.last_stage == Stage::AsmIr {
+        return Ok(CompileOutput {
+            assembly: String::new(),
+            warnings,
+        });
+    }
+
+    let assembly = emit_program(&asm, tree.interner(), options.debug_info);
+    let mut assembly = String::from_utf8(assembly).expect("assembly should be UTF-8");
+    if options.emit_ident {
+        // Like gcc, name the compiler in a .ident directive, which ends up
+        // in the object's .comment section.
+        let ident = format!("zcc {}", env!("CARGO_PKG_VERSION"));
+        assembly = format!("# {ident}\n{assembly}\t.ident\t\"{ident}\"\n");
+    }
+    if let Some(observer) = observer.as_mut() {
+        observer.observe(Stage::Assembly, &assembly);
+    }
+    Ok(CompileOutput { assembly, warnings })
+}
+
+pub(crate) fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_and_char_paths_agree() {
+        let sources = [
+            "int main(void) {\n    return 2;\n}\n",
+            "int x = 3;\nvoid f(void) { __asm__(\"nop\"); return; }",
+            "return -~!2 != 3 <<= >>= -- - -;",
+            "// a comment\n/* another\n one */ int 12ab $ \"open",
+            "/* unterminated",
+            "\t\r\n int\tmain",
+        ];
+        for source in sources {
+            let mut bytes = TokenStream::default();
+            lex_ascii(source, &mut bytes, MAX_IDENTIFIER_LEN);
+            let mut chars = TokenStream::default();
+            lex_chars(source, &mut chars, MAX_IDENTIFIER_LEN);
+            assert_eq!(bytes, chars, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn char_path_on_mixed_input() {
+        // A no-break space is whitespace, and a letter the lexer doesn't
+        // know is an error token like `@` is on the byte path.
+        let mixed = "int main(void) {\n\u{a0}return \"h\u{e9}llo\"; } \u{e9} @";
+        let ascii = "int main(void) {\n return \"hello\"; } @ @";
+        let (mixed, ascii) = (lexer(mixed.into()), lexer(ascii.into()));
+        // Columns count characters, offsets bytes.
+        let spans = |tokens: &TokenStream| {
+            let spans = tokens.iter().map(|t| (t.span.line, t.span.col));
+            spans.collect::<Vec<_>>()
+        };
+        assert_eq!(mixed.kinds(), ascii.kinds());
+        assert_eq!(spans(&mixed), spans(&ascii));
+        assert_eq!(mixed.texts()[7], "\"h\u{e9}llo\"");
+    }
+
+    #[test]
+    fn line_markers_set_the_position() {
+        let source = "# 1 \"f.c\"\n# 12 \"f.c\"\nint main(void) {\n    return 2\n}\n";
+        for tokens in [lexer(source.into()), lexer(format!("{source}// \u{e9}"))] {
+            let span = tokens[0].span;
+            assert_eq!(
+                (tokens.interner().resolve(span.file), span.line),
+                ("f.c", 12)
+            );
+            let mut parser = Parser::new(tokens);
+            parse_program(&mut parser);
+            assert_eq!(parser.errors, ["f.c:14:1: expected Semicolon"]);
+        }
+        // Only at the start of a line.
+        assert!(lexer("int x; # 1 \"f.c\"".into()).is_err());
+    }
+
+    #[test]
+    fn relex_from_matches_the_full_lex() {
+        let source = "int x = 3;\nint y;\nint main(void) {\n    return 2;\n}\n";
+        let mut full = lexer(source.into());
+        for (byte_offset, skipped) in [(0, 0), (11, 5), (18, 8), (22, 9), (39, 14)] {
+            let tail = relex_from(source, byte_offset, full.interner_mut());
+            let spans =
+                |tokens: &[Token]| tokens.iter().map(|token| token.span).collect::<Vec<_>>();
+            assert_eq!(
+                spans(&full.as_slice()[skipped..]),
+                spans(&tail),
+                "from {byte_offset}"
+            );
+            assert_eq!(full.as_slice()[skipped..], tail[..], "from {byte_offset}");
+        }
+        assert_eq!(full.interner(), lexer(source.into()).interner());
+    }
+
+    #[test]
+    fn relex_from_after_a_multi_byte_character() {
+        // `é` is two bytes but one column.
+        let source = "int main(void) { __asm__(\"é\"); return 2; }";
+        let mut full = lexer(source.into());
+        let semicolon = source.find(';').unwrap();
+        let tail = relex_from(source, semicolon, full.interner_mut());
+        assert_eq!(tail[0].kind, TokenKind::Semicolon);
+        assert_eq!((tail[0].span.line, tail[0].span.col), (1, 30));
+        assert_eq!(full.as_slice()[10..], tail[..]);
+
+        // Inside the `é`, and past the end.
+        let e = source.find('é').unwrap();
+        let from_e = relex_from(source, e, full.interner_mut());
+        assert_eq!(relex_from(source, e + 1, full.interner_mut()), from_e);
+        assert!(relex_from(source, source.len() + 1, full.interner_mut()).is_empty());
+    }
+
+    fn recover(source: &str) -> (String, Vec<String>) {
+        let mut parser = Parser::new(lexer(source.into()));
+        parse_program(&mut parser);
+        let errors = std::mem::take(&mut parser.errors);
+        let tree = parser.build_tree();
+        let mut pretty = String::new();
+        Parser::pretty_print(&tree, tree.root(), 0, true, &mut pretty);
+        (pretty, errors)
+    }
+
+    #[test]
+    fn missing_close_brace_leaves_an_error_tree() {
+        let (pretty, errors) = recover("int main(void) {\n    return 2;\n");
+        assert_eq!(errors, ["end of input: expected CloseBrace"]);
+        assert_eq!(
+            pretty,
+            "Program(\n    Function(\n        name = \"main\"\n        type = \"int\"\n        \
+             params = \"void\"\n        body = Return(\n            Constant(2)\n        )\n        \
+             body = ErrorTree(\n        )\n    )\n)\n"
+        );
+    }
+
+    #[test]
+    fn missing_open_brace_keeps_the_body() {
+        let (pretty, errors) = recover("int main(void)\n    return 2;\n}\n");
+        assert_eq!(errors, ["2:5: expected OpenBrace"]);
+        assert_eq!(
+            pretty,
+            "Program(\n    Function(\n        name = \"main\"\n        type = \"int\"\n        \
+             params = \"void\"\n        ErrorTree(\n        )\n        body = Return(\n            \
+             Constant(2)\n        )\n    )\n)\n"
+        );
+    }
+
+    #[test]
+    fn negated_literals_are_folded_before_the_range_check() {
+        for source in [
+            "int main(void) { return -2147483648; }",
+            "int main(void) { return -(2147483648); }",
+        ] {
+            let asm = compile_to_asm(source, None).unwrap();
+            assert!(asm.contains("movl\t$-2147483648, %eax"), "{asm}");
+        }
+        for source in [
+            "int main(void) { return 2147483648; }",
+            "int main(void) { return -2147483649; }",
+            "int main(void) { return ~2147483648; }",
+        ] {
+            let result = compile_to_asm(source, None);
+            assert!(matches!(result, Err(CompileError::Semantic(_))), "{source}");
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let ok = format!(
+            "int main(void) {{ return {}2; }}",
+            "~".repeat(MAX_NESTING - 1)
+        );
+        assert!(compile_to_asm(&ok, None).is_ok());
+        let deep = format!("int main(void) {{ return {}2; }}", "~".repeat(200_000));
+        match compile_to_asm(&deep, None) {
+            Err(CompileError::Parse(errors)) => {
+                assert_eq!(errors, ["1:281: expression nested too deeply"])
+            }
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn exit_code_warnings_fold_constants() {
+        let warned = |source: &str| {
+            let mut parser = Parser::new(lexer(source.into()));
+            parse_program(&mut parser);
+            let tree = parser.build_tree();
+            exit_code_warnings(&tree)
+                .into_iter()
+                .map(|warning| warning.message)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            warned("int main(void) { return (256); }"),
+            ["main returns 256, which the exit status truncates to 0"]
+        );
+        assert_eq!(
+            warned("int main(void) { return -1; }"),
+            ["main returns -1, which the exit status truncates to 255"]
+        );
+        assert_eq!(
+            warned("int main(void) { return ~0; }"),
+            ["main returns -1, which the exit status truncates to 255"]
+        );
+        assert!(warned("int main(void) { return 42; }").is_empty());
+        assert!(warned("int main(void) { return -(-3); }").is_empty());
+        assert!(warned("int main(void) { return !-3; }").is_empty());
+    }
+
+    #[test]
+    fn only_return_and_asm_are_statements() {
+        let errors = |source: &str| {
+            let mut parser = Parser::new(lexer(source.into()));
+            parse_program(&mut parser);
+            parser.errors
+        };
+        assert!(errors("int main(void) { __asm__(\"nop\"); return 2; }").is_empty());
+        assert_eq!(
+            errors("int main(void) { int 5; }"),
+            [
+                "1:18: expected a statement",
+                "1:22: expected a statement",
+                "1:23: expected a statement"
+            ]
+        );
+        assert_eq!(
+            errors("void f(void) { struct; }"),
+            ["1:16: expected a statement", "1:22: expected a statement"]
+        );
+        assert_eq!(
+            errors("return main(void) { return 2; }"),
+            ["1:1: expected a type specifier"]
+        );
+        assert_eq!(errors("return x;"), ["1:1: expected a type specifier"]);
+    }
+
+    #[test]
+    fn parameter_lists() {
+        let errors = |source: &str| {
+            let mut parser = Parser::new(lexer(source.into()));
+            parse_program(&mut parser);
+            parser.errors
+        };
+        assert!(errors("int foo(int x);").is_empty());
+        assert!(errors("int foo(int x, struct point p);").is_empty());
+        assert!(errors("int foo(int, int);").is_empty());
+        assert!(errors("int f(int a, int b) { return 1; }").is_empty());
+        assert_eq!(
+            errors("int main(return) { return 0; }"),
+            ["1:10: expected a type specifier"]
+        );
+        assert_eq!(
+            errors("int main(int) { return 0; }"),
+            ["1:10: parameter name omitted"]
+        );
+        assert_eq!(
+            errors("int main() { return 0; }"),
+            ["1:10: expected a parameter list"]
+        );
+        assert_eq!(
+            errors("int f(int a, void);"),
+            ["1:14: void has to be the only parameter"]
+        );
+        assert_eq!(
+            errors("int f(int a,);"),
+            ["1:13: expected a type specifier"]
+        );
+        assert_eq!(
+            errors("int f(int a int b);")[0],
+            "1:13: expected CloseParen"
+        );
+    }
+
+    fn syntax_tree(source: &str) -> SyntaxTree {
+        let mut parser = Parser::new(lexer(source.into()));
+        parse_program(&mut parser);
+        assert_eq!(parser.errors, Vec::<String>::new());
+        parser.build_tree()
+    }
+
+    #[test]
+    fn offset_queries() {
+        let source = "int x = 3;\n\nint main(void) {\n    return -(2);\n}\n";
+        let tree = syntax_tree(source);
+        let at = |needle: &str| source.find(needle).unwrap();
+        let token = |offset| tree.token_at_offset(offset).map(|token| token.kind);
+        let node = |offset| tree.kind(tree.node_at_offset(offset));
+        let cases = [
+            (0, Some(TokenKind::Keyword), TreeKind::GlobalVar),
+            (at("x"), Some(TokenKind::Identifier), TreeKind::GlobalVar),
+            // Right after `x`, before the space.
+            (
+                at("x") + 1,
+                Some(TokenKind::Identifier),
+                TreeKind::GlobalVar,
+            ),
+            (at("\n\n") + 1, None, TreeKind::Program),
+            (at("ain"), Some(TokenKind::Identifier), TreeKind::Function),
+            // Between two tokens, the one on the left wins.
+            (at("void"), Some(TokenKind::OpenParen), TreeKind::Function),
+            (at("void") + 1, Some(TokenKind::Keyword), TreeKind::Params),
+            (at("{"), Some(TokenKind::OpenBrace), TreeKind::Function),
+            (at("{") + 1, Some(TokenKind::OpenBrace), TreeKind::Function),
+            (at("   return"), None, TreeKind::Function),
+            (at("return"), Some(TokenKind::Keyword), TreeKind::Return),
+            (at("("), Some(TokenKind::Identifier), TreeKind::Function),
+            (at("-("), Some(TokenKind::Minus), TreeKind::Unary),
+            (at("(2"), Some(TokenKind::Minus), TreeKind::Unary),
+            (at("2") + 1, Some(TokenKind::Constant), TreeKind::Paren),
+            (at("}"), Some(TokenKind::CloseBrace), TreeKind::Function),
+            (source.len(), None, TreeKind::Program),
+        ];
+        for (offset, kind, node_kind) in cases {
+            assert_eq!(
+                (token(offset), node(offset)),
+                (kind, node_kind),
+                "at {offset}"
+            );
+        }
+
+        let ancestors: Vec<TreeKind> = tree
+            .ancestors(tree.node_at_offset(at("2")))
+            .map(|id| tree.kind(id))
+            .collect();
+        assert_eq!(
+            ancestors,
+            [
+                TreeKind::Paren,
+                TreeKind::Unary,
+                TreeKind::Return,
+                TreeKind::Function,
+                TreeKind::Program
+            ]
+        );
+    }
+
+    #[test]
+    fn dot_escape_quotes_braces_and_backslashes() {
+        assert_eq!(dot_escape("main"), "main");
+        assert_eq!(dot_escape("\"a\\b\""), "\\\"a\\\\b\\\"");
+        assert_eq!(dot_escape("{x|y}"), "\\{x\\|y\\}");
+        assert_eq!(dot_escape("<p>"), "\\<p\\>");
+        assert_eq!(dot_escape("a\nb"), "a\\nb");
+    }
+
+    #[test]
+    fn tree_to_dot_has_a_node_per_tree_and_token() {
+        let dot = tree_to_dot(&syntax_tree("int main(void) {\n    return 2;\n}\n"));
+        assert!(dot.starts_with("digraph {\n"), "{dot}");
+        assert!(dot.ends_with("}\n"), "{dot}");
+        assert_eq!(dot.matches("shape=box").count(), 4);
+        assert_eq!(dot.matches("shape=ellipse").count(), 10);
+        assert_eq!(dot.matches(" -> ").count(), 13);
+
+        let dot = tree_to_dot(&syntax_tree("int main(void) { __asm__(\"{\\\\}\"); }"));
+        assert!(
+            dot.contains("label=\"StringLiteral \\\"\\\"\\{\\\\\\\\\\}\\\"\\\"\""),
+            "{dot}"
+        );
+    }
+
+    #[test]
+    fn observer_sees_every_stage_in_order() {
+        let mut stages: Vec<(Stage, String)> = vec![];
+        let asm = compile_to_asm("int main(void) { return 2; }", Some(&mut stages)).unwrap();
+        let seen: Vec<Stage> = stages.iter().map(|(stage, _)| *stage).collect();
+        assert_eq!(seen, Stage::ALL);
+        assert_eq!(stages[4].1, asm);
+
+        let mut stages: Vec<(Stage, String)> = vec![];
+        assert!(compile_to_asm("int main(void) { return 2 }", Some(&mut stages)).is_err());
+        assert_eq!(stages.len(), 1);
+    }
+
+    #[test]
+    fn token_stats_of_the_canonical_program() {
+        let tokens = lexer("int main(void) {\n    return 2;\n}\n".into());
+        let stats: Vec<(TokenKind, usize)> = token_stats(tokens.as_slice()).into_iter().collect();
+        assert_eq!(
+            stats,
+            [
+                (TokenKind::Identifier, 1),
+                (TokenKind::Constant, 1),
+                (TokenKind::Keyword, 3),
+                (TokenKind::OpenParen, 1),
+                (TokenKind::CloseParen, 1),
+                (TokenKind::OpenBrace, 1),
+                (TokenKind::CloseBrace, 1),
+                (TokenKind::Semicolon, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn global_variables() {
+        let (pretty, errors) = recover("int g;");
+        assert!(errors.is_empty());
+        assert_eq!(
+            pretty,
+            "Program(\n    GlobalVar(\n        name = \"g\"\n        type = \"int\"\n    )\n)\n"
+        );
+
+        let (pretty, errors) = recover("int g = 3;");
+        assert!(errors.is_empty());
+        assert_eq!(
+            pretty,
+            "Program(\n    GlobalVar(\n        name = \"g\"\n        type = \"int\"\n        init = Constant(3)\n    )\n)\n"
+        );
+
+        let (pretty, errors) = recover("int g = 3;\nint main(void) { return 2; }");
+        assert!(errors.is_empty());
+        assert!(
+            pretty.starts_with("Program(\n    GlobalVar(\n        name = \"g\"\n        type = \"int\"\n        init = Constant(3)\n    )\n    Function(\n        name = \"main\"\n        type = \"int\"\n"),
+            "{pretty}"
+        );
+    }
+
+    #[test]
+    fn frames_are_a_multiple_of_the_stack_alignment() {
+        let sizes: Vec<i64> = [0, 1, 3, 4, 5]
+            .into_iter()
+            .map(|slots| frame_size(slots, STACK_ALIGN))
+            .collect();
+        assert_eq!(sizes, [0, 16, 16, 16, 32]);
+    }
+
+    // No operator the parser knows produces a Binary yet, so the program is
+    // put together by hand.
+    #[test]
+    fn binary_instructions_go_through_eax() {
+        let mut interner = Interner::default();
+        let [tmp0, tmp1, tmp2] = ["tmp.0", "tmp.1", "tmp.2"].map(|name| interner.intern(name));
+        let binary = |op, src1, src2, dst| TackyInstruction::Binary {
+            op,
+            src1,
+            src2,
+            dst: TackyVal::Var(dst),
+        };
+        let program = TackyProgram {
+            functions: vec![TackyFunction {
+                identifier: interner.intern("main"),
+                instructions: vec![
+                    binary(
+                        TackyBinaryOperator::Add,
+                        TackyVal::Constant(1),
+                        TackyVal::Constant(2),
+                        tmp0,
+                    ),
+                    binary(
+                        TackyBinaryOperator::Subtract,
+                        TackyVal::Var(tmp0),
+                        TackyVal::Constant(3),
+                        tmp1,
+                    ),
+                    binary(
+                        TackyBinaryOperator::Multiply,
+                        TackyVal::Var(tmp1),
+                        TackyVal::Var(tmp0),
+                        tmp2,
+                    ),
+                    TackyInstruction::Return(Some(TackyVal::Var(tmp2))),
+                ],
+            }],
+            statics: vec![],
+        };
+        let asm = generate_assembly(&program, STACK_ALIGN);
+        let assembly = String::from_utf8(emit_program(&asm, &interner, false)).unwrap();
+        let body: Vec<&str> = assembly
+            .lines()
+            .filter(|line| line.contains("%eax"))
+            .collect();
+        assert_eq!(
+            body,
+            [
+                "\tmovl\t$1, %eax",
+                "\taddl\t$2, %eax",
+                "\tmovl\t%eax, -4(%rbp)",
+                "\tmovl\t-4(%rbp), %eax",
+                "\tsubl\t$3, %eax",
+                "\tmovl\t%eax, -8(%rbp)",
+                "\tmovl\t-8(%rbp), %eax",
+                "\timull\t-4(%rbp), %eax",
+                "\tmovl\t%eax, -12(%rbp)",
+                "\tmovl\t-12(%rbp), %eax",
+            ],
+            "{assembly}"
+        );
+    }
+
+    #[test]
+    fn debug_info_gives_each_statement_a_loc() {
+        let assembly = |source: &str, debug_info| {
+            let options = CompileOptions {
+                debug_info,
+                ..CompileOptions::default()
+            };
+            compile_source(source, &options, None).unwrap().assembly
+        };
+        let source = "# 1 \"t.c\"\nint main(void) {\n    __asm__(\"nop\");\n    return 2;\n}\n";
+        let with = assembly(source, true);
+        assert!(with.starts_with("\t.file\t1 \"t.c\"\n"), "{with}");
+        assert!(
+            with.contains("\t.loc\t1 2 5\n\tnop\n\t.loc\t1 3 5\n"),
+            "{with}"
+        );
+        assert!(!assembly(source, false).contains(".loc"));
+        // Without line markers there's no file name for them.
+        assert!(!assembly("int main(void) { return 2; }", true).contains(".file"));
+    }
+
+    #[test]
+    fn stack_align_sets_the_frame_rounding() {
+        // Three temporaries, 12 bytes.
+        let source = "int main(void) { return -~-2; }";
+        let frame = |stack_align| {
+            let options = CompileOptions {
+                stack_align,
+                ..CompileOptions::default()
+            };
+            let assembly = compile_source(source, &options, None).unwrap().assembly;
+            let subq = assembly.lines().find(|line| line.contains("subq"));
+            subq.unwrap().to_string()
+        };
+        assert_eq!(frame(STACK_ALIGN), "\tsubq\t$16, %rsp");
+        assert_eq!(frame(32), "\tsubq\t$32, %rsp");
+        let options = CompileOptions {
+            stack_align: 12,
+            ..CompileOptions::default()
+        };
+        assert!(matches!(
+            compile_source(source, &options, None),
+            Err(CompileError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn frames_over_max_stack_are_refused() {
+        // Ten temporaries, 40 bytes in a 48-byte frame.
+        let source = format!("int main(void) {{ return {}2; }}", "-~".repeat(5));
+        let options = CompileOptions {
+            max_stack: 32,
+            ..CompileOptions::default()
+        };
+        match compile_source(&source, &options, None) {
+            Err(CompileError::Codegen(message)) => assert_eq!(
+                message,
+                "main: stack frame of 48 bytes is over the limit of 32"
+            ),
+            result => panic!("{result:?}"),
+        }
+        let options = CompileOptions {
+            max_stack: 48,
+            ..options
+        };
+        assert!(compile_source(&source, &options, None).is_ok());
+    }
+
+    #[test]
+    fn stack_map_gives_every_temporary_its_own_offset() {
+        let source = "int main(void) { return -~2; }";
+        let mut tree = syntax_tree(source);
+        let tacky = generate_tacky(&mut tree).unwrap();
+        let asm = generate_assembly(&tacky, STACK_ALIGN);
+        assert_eq!(
+            stack_map(&asm, tree.interner()),
+            "main:\n    tmp.0 -4(%rbp)\n    tmp.1 -8(%rbp)\n"
+        );
+    }
+
+    #[test]
+    fn falling_off_the_end_returns() {
+        let source = "int helper(void) { __asm__(\"nop\"); }\n\
+                      void quiet(void) { __asm__(\"nop\"); }";
+        let mut tree = syntax_tree(source);
+        let tacky = generate_tacky(&mut tree).unwrap();
+        let last = |index: usize| tacky.functions[index].instructions.last().cloned();
+        assert_eq!(
+            last(0),
+            Some(TackyInstruction::Return(Some(TackyVal::Constant(0))))
+        );
+        assert_eq!(last(1), Some(TackyInstruction::Return(None)));
+    }
+
+    #[test]
+    fn globals_go_to_data_and_bss() {
+        let asm = compile_to_asm("int g;\nint z = 0;\nint h = 3;", None).unwrap();
+        assert_eq!(
+            asm,
+            "\t.globl\tg\n\t.bss\n\t.balign\t4\ng:\n\t.zero\t4\n\
+             \t.globl\tz\n\t.bss\n\t.balign\t4\nz:\n\t.zero\t4\n\
+             \t.globl\th\n\t.data\n\t.balign\t4\nh:\n\t.long\t3\n"
+        );
+    }
+
+    #[test]
+    fn integer_constant_limits() {
+        let compile = |constant: &str| {
+            compile_to_asm(&format!("int main(void) {{ return {constant}; }}"), None)
+        };
+        assert!(compile("2147483647").unwrap().contains("$2147483647"));
+        assert!(compile("-2147483648").unwrap().contains("$-2147483648"));
+        for constant in ["2147483648", "99999999999999999999"] {
+            match compile(constant) {
+                Err(CompileError::Semantic(errors)) => assert_eq!(
+                    errors,
+                    [format!("1:25: integer constant out of range: {constant}")]
+                ),
+                result => panic!("{result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn implicit_int_warns_under_c89_and_is_an_error_after() {
+        let source = b"main(void){return 0;}";
+        let options = |standard| CompileOptions {
+            standard,
+            ..CompileOptions::default()
+        };
+        let output = compile_bytes(source, options(Standard::C89)).unwrap();
+        assert_eq!(
+            output.warnings,
+            [Warning {
+                code: diagnostics::IMPLICIT_INT,
+                message: "1:1: type specifier missing, defaults to int".into()
+            }]
+        );
+        match compile_bytes(source, options(Standard::C99)) {
+            Err(CompileError::Parse(errors)) => {
+                assert_eq!(errors, ["1:1: type specifier missing, defaults to int"])
+            }
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn identifier_length_limit() {
+        let under = lex_with_limit("int abcd;".into(), 4);
+        assert!(under.errors().is_empty());
+        assert_eq!(under.texts(), ["int", "abcd", ";"]);
+
+        let over = lex_with_limit("int abcde;".into(), 4);
+        assert_eq!(
+            over.errors(),
+            ["1:5: identifier too long: 5 bytes, the limit is 4"]
+        );
+        // The \u{e9} takes the char path, which counts bytes too.
+        let over = lex_with_limit("int abc\u{e9};".into(), 4);
+        assert_eq!(
+            over.errors(),
+            ["1:5: identifier too long: 5 bytes, the limit is 4"]
+        );
+    }
+
+    #[test]
+    fn inline_asm_is_passed_through() {
+        let (pretty, errors) = recover("int main(void) { __asm__(\"nop\"); return 0; }");
+        assert!(errors.is_empty());
+        assert!(pretty.contains("InlineAsm("), "{pretty}");
+        let asm = compile_to_asm("int main(void) { __asm__(\"nop\"); return 0; }", None).unwrap();
+        assert!(asm.lines().any(|line| line.trim() == "nop"), "{asm}");
+    }
+
+    #[test]
+    fn compile_errors_display_their_messages() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "not found");
+        let errors = [
+            (CompileError::Io("a.c".into(), not_found), "a.c: not found"),
+            (CompileError::Lex(vec!["1:1: x".into()]), "1:1: x"),
+            (
+                CompileError::Parse(vec!["1:1: a".into(), "2:1: b".into()]),
+                "1:1: a\n2:1: b",
+            ),
+            (CompileError::Semantic(vec!["1:1: c".into()]), "1:1: c"),
+            (CompileError::Codegen("no structs".into()), "no structs"),
+            (CompileError::Toolchain("gcc failed".into()), "gcc failed"),
+            (
+                CompileError::Internal("parsing panicked".into()),
+                "parsing panicked",
+            ),
+            (CompileError::Usage("no main".into()), "no main"),
+        ];
+        for (error, display) in errors {
+            assert_eq!(error.to_string(), display);
+        }
+        let error = CompileError::Parse(vec!["1:1: a".into(), "2:1: b".into()]);
+        assert_eq!(error.messages(), ["1:1: a", "2:1: b"]);
+    }
+
+    #[test]
+    fn werror_codes_promote_only_the_named_warnings() {
+        let source = "int main(void) {\n    return 256;\n    return 2;\n}\n";
+        let options = CompileOptions {
+            warn_exit_code: true,
+            ..CompileOptions::default()
+        };
+        let output = compile_source(source, &options, None).unwrap();
+        let codes: Vec<&str> = output.warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(
+            codes,
+            [diagnostics::UNREACHABLE_CODE, diagnostics::EXIT_CODE_RANGE]
+        );
+
+        let options = CompileOptions {
+            werror_codes: vec!["W0004".into()],
+            ..options
+        };
+        match compile_source(source, &options, None) {
+            Err(CompileError::Semantic(errors)) => assert_eq!(
+                errors,
+                ["3:5: code after return in main will never be executed [W0004]"]
+            ),
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn missing_return_is_warned_at_the_closing_brace() {
+        let source = "int f(void) {\n    __asm__(\"nop\");\n}\nint main(void) {\n}\n";
+        let output = compile_source(source, &CompileOptions::default(), None).unwrap();
+        assert_eq!(
+            output.warnings,
+            [Warning {
+                code: diagnostics::MISSING_RETURN,
+                message: "3:1: control reaches end of non-void function f".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn promoted_parser_and_pass_warnings_are_both_semantic_errors() {
+        let source = "f(void) {\n}\n";
+        let options = CompileOptions {
+            standard: Standard::C89,
+            werror_codes: vec!["W0001".into(), "W0002".into()],
+            ..CompileOptions::default()
+        };
+        match compile_source(source, &options, None) {
+            Err(CompileError::Semantic(errors)) => assert_eq!(
+                errors,
+                [
+                    "1:1: type specifier missing, defaults to int [W0001]",
+                    "2:1: control reaches end of non-void function f [W0002]"
+                ]
+            ),
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn last_stage_stops_the_pipeline() {
+        let options = CompileOptions {
+            last_stage: Stage::Tacky,
+            ..CompileOptions::default()
+        };
+        let mut stages: Vec<(Stage, String)> = vec![];
+        let output = compile_source("int main(void) { return 2; }", &options, Some(&mut stages));
+        assert_eq!(output.unwrap().assembly, "");
+        let seen: Vec<Stage> = stages.iter().map(|(stage, _)| *stage).collect();
+        assert_eq!(seen, [Stage::Tokens, Stage::Cst, Stage::Tacky]);
+    }
+
+    #[test]
+    fn only_drops_the_other_functions_before_lowering() {
+        let source = "int x = 1;\nint f(void) { return 1; }\nint main(void) { return 2; }\n";
+        let options = CompileOptions {
+            last_stage: Stage::Tacky,
+            only: Some("main".into()),
+            ..CompileOptions::default()
+        };
+        let mut stages: Vec<(Stage, String)> = vec![];
+        compile_source(source, &options, Some(&mut stages)).unwrap();
+        let (_, tacky) = stages.last().unwrap();
+        assert_eq!(tacky.matches("TackyFunction {").count(), 1, "{tacky}");
+        assert_eq!(tacky.matches("TackyStaticVariable {").count(), 1, "{tacky}");
+
+        let options = CompileOptions {
+            only: Some("missing".into()),
+            ..CompileOptions::default()
+        };
+        match compile_source(source, &options, None) {
+            Err(CompileError::Usage(message)) => assert_eq!(message, "no function named missing"),
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn compile_bytes_checks_utf8() {
+        let output = compile_bytes(b"int main(void) { return 2; }", CompileOptions::default());
+        assert!(output.unwrap().assembly.contains("main:"));
+        match compile_bytes(b"int main(void) { \xff }", CompileOptions::default()) {
+            Err(CompileError::Lex(errors)) => assert_eq!(errors, ["invalid UTF-8 at byte 17"]),
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn explain_tokens_traces_the_rules_in_order() {
+        let source = "int x;";
+        let trace = explain_tokens(source, &lexer(source.into()));
+        let lines: Vec<&str> = trace.lines().collect();
+        let expected = [
+            (
+                "Keyword \"int\"",
+                "^(void|int|return|__asm__|struct)\\b",
+                " x;",
+            ),
+            ("trim whitespace", "", "x;"),
+            ("Identifier \"x\"", "^([a-zA-Z_]\\w*)\\b", ";"),
+            ("Semicolon \";\"", "punctuation", ""),
+        ];
+        assert_eq!(lines.len(), expected.len(), "{trace}");
+        for (line, (token, rule, rest)) in lines.iter().zip(expected) {
+            assert!(line.starts_with(token), "{line}");
+            assert!(line.contains(rule), "{line}");
+            assert!(line.ends_with(&format!("rest: {rest:?}")), "{line}");
+            assert_eq!(line.find("rest: "), Some(61), "{line}");
+        }
+    }
+
+    #[test]
+    fn code_after_return_warns_at_the_first_unreachable_statement() {
+        let warned = |source: &str| {
+            unreachable_code_warnings(&syntax_tree(source))
+                .into_iter()
+                .map(|warning| warning.message)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            warned("int main(void) {\n    return 1;\n    return 2;\n    return 3;\n}"),
+            ["3:5: code after return in main will never be executed"]
+        );
+        assert!(warned("int main(void) { return 1; }").is_empty());
+        assert!(warned("int main(void) { __asm__(\"nop\"); return 1; }").is_empty());
+    }
+
+    #[test]
+    fn bang_and_not_equal() {
+        use TokenKind::*;
+        assert_eq!(lexer("!a".into()).kinds(), [Bang, Identifier]);
+        assert_eq!(
+            lexer("a != b".into()).kinds(),
+            [Identifier, NotEqual, Identifier]
+        );
+        assert_eq!(
+            lexer("a!=!b".into()).kinds(),
+            [Identifier, NotEqual, Bang, Identifier]
+        );
+        assert_eq!(lexer("! =".into()).kinds(), [Bang, Equals]);
+    }
+
+    #[test]
+    fn logical_not_compares_with_zero() {
+        let asm = compile_to_asm("int main(void) { return !0; }", None).unwrap();
+        assert!(asm.contains("\tcmpl\t$0, "), "{asm}");
+        assert!(asm.contains("\tsete\t%al\n"), "{asm}");
+    }
+
+    #[test]
+    fn minus_tilde_and_decrement() {
+        use TokenKind::*;
+        assert_eq!(lexer("-".into()).kinds(), [Minus]);
+        assert_eq!(lexer("~".into()).kinds(), [Tilde]);
+        assert_eq!(lexer("--".into()).kinds(), [Decrement]);
+        assert_eq!(lexer("- -".into()).kinds(), [Minus, Minus]);
+        assert_eq!(
+            lexer("-~-2".into()).kinds(),
+            [Minus, Tilde, Minus, Constant]
+        );
+        assert_eq!(lexer("---".into()).kinds(), [Decrement, Minus]);
+    }
+
+    #[test]
+    fn arithmetic_operators() {
+        use TokenKind::*;
+        let tokens = lexer("1+2*3/4%5".into());
+        assert!(!tokens.is_err());
+        assert_eq!(
+            tokens.kinds(),
+            [Constant, Plus, Constant, Asterisk, Constant, Slash, Constant, Percent, Constant]
+        );
+        assert_eq!(
+            tokens.texts(),
+            ["1", "+", "2", "*", "3", "/", "4", "%", "5"]
+        );
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let line = lexer("int main(void) { return 2; } // trailing".into());
+        assert!(!line.is_err());
+        assert!(line.has_comments());
+        assert_eq!(line.texts().last(), Some(&"}"));
+
+        let block = lexer("int /* one\n two\n */ main(void) { return 2; }".into());
+        assert!(!block.is_err());
+        assert_eq!(block.texts()[..2], ["int", "main"]);
+        assert_eq!((block[1].span.line, block[1].span.col), (3, 5));
+
+        assert!(!lexer("int main(void) { return 2; }".into()).has_comments());
+        assert!(lexer("int /* open".into()).is_err());
+    }
+
+    #[test]
+    fn thousands_of_identifiers() {
+        let names: Vec<String> = (0..5000).map(|i| format!("name{i}")).collect();
+        let source: String = names.iter().map(|name| format!("int {name};\n")).collect();
+        let tokens = lexer(source.clone());
+        assert!(!tokens.is_err());
+        assert_eq!(tokens.len(), 3 * names.len());
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(tokens.text(&tokens[3 * i + 1]), name);
+        }
+        let mut chars = TokenStream::default();
+        lex_chars(&source, &mut chars, MAX_IDENTIFIER_LEN);
+        assert_eq!(tokens, chars);
+    }
+
+    // A benchmark rather than a test, the byte path is the reason to have two
+    // lexers: cargo test --release -- --ignored --nocapture lex_ascii_speed
+    #[test]
+    #[ignore = "benchmark"]
+    fn lex_ascii_speed() {
+        let function = "int f{i}(void) {\n    /* {i} */ return -~(!{i} << 2) >= 3;\n}\n";
+        let source: String = (0..50_000)
+            .map(|i| function.replace("{i}", &i.to_string()))
+            .collect();
+        let time = |lex: fn(&str, &mut TokenStream, usize)| {
+            let mut best = std::time::Duration::MAX;
+            let mut tokens = TokenStream::default();
+            for _ in 0..5 {
+                tokens = TokenStream::default();
+                let start = std::time::Instant::now();
+                lex(&source, &mut tokens, MAX_IDENTIFIER_LEN);
+                best = best.min(start.elapsed());
+            }
+            (best, tokens)
+        };
+        let (bytes_time, bytes) = time(lex_ascii);
+        let (chars_time, chars) = time(lex_chars);
+        assert_eq!(bytes, chars);
+        let megabytes = source.len() as f64 / 1e6;
+        for (name, elapsed) in [("lex_ascii", bytes_time), ("lex_chars", chars_time)] {
+            let rate = megabytes / elapsed.as_secs_f64();
+            println!("{name}: {megabytes:.1} MB in {elapsed:?}, {rate:.0} MB/s");
+        }
+        assert!(bytes_time < chars_time);
+    }
+
+    #[test]
+    fn adjacent_logical_and_relational_operators() {
+        use TokenKind::*;
+        assert_eq!(
+            lexer("a<=b==c&&!d".into()).kinds(),
+            [
+                Identifier, LessEqual, Identifier, EqualEqual, Identifier, LogicalAnd, Bang,
+                Identifier
+            ]
+        );
+        assert_eq!(
+            lexer("a<b>c>=d||e".into()).kinds(),
+            [
+                Identifier,
+                Less,
+                Identifier,
+                Greater,
+                Identifier,
+                GreaterEqual,
+                Identifier,
+                LogicalOr,
+                Identifier
+            ]
+        );
+    }
+
+    #[test]
+    fn bitwise_and_shift_operators() {
+        use TokenKind::*;
+        assert_eq!(
+            lexer("a << 2 >> 1 & 3 | 4 ^ 5".into()).kinds(),
+            [
+                Identifier, ShiftLeft, Constant, ShiftRight, Constant, Ampersand, Constant, Pipe,
+                Constant, Caret, Constant
+            ]
+        );
+        assert_eq!(lexer("&&&".into()).kinds(), [LogicalAnd, Ampersand]);
+    }
+
+    #[test]
+    fn assignment_operators() {
+        use TokenKind::*;
+        assert_eq!(
+            lexer("a = b".into()).kinds(),
+            [Identifier, Equals, Identifier]
+        );
+        assert_eq!(
+            lexer("a == b".into()).kinds(),
+            [Identifier, EqualEqual, Identifier]
+        );
+        assert_eq!(
+            lexer("a <<= b".into()).kinds(),
+            [Identifier, ShiftLeftEqual, Identifier]
+        );
+        assert_eq!(
+            lexer("a << = b".into()).kinds(),
+            [Identifier, ShiftLeft, Equals, Identifier]
+        );
+        assert_eq!(
+            lexer("+= -= *= /= %= &= |= ^= >>=".into()).kinds(),
+            [
+                PlusEqual,
+                MinusEqual,
+                AsteriskEqual,
+                SlashEqual,
+                PercentEqual,
+                AmpersandEqual,
+                PipeEqual,
+                CaretEqual,
+                ShiftRightEqual
+            ]
+        );
+    }
+
+    #[test]
+    fn struct_declarations() {
+        let (pretty, errors) = recover("struct point { int x; int y; };");
+        assert!(errors.is_empty());
+        assert_eq!(
+            pretty,
+            "Program(\n    StructDecl(\n        name = \"point\"\n        member = MemberDecl(\n            \
+             name = \"x\"\n            type = \"int\"\n        )\n        member = MemberDecl(\n            \
+             name = \"y\"\n            type = \"int\"\n        )\n    )\n)\n"
+        );
+
+        let (pretty, errors) = recover("struct point p;");
+        assert!(errors.is_empty());
+        assert_eq!(
+            pretty,
+            "Program(\n    GlobalVar(\n        name = \"p\"\n        type = \"struct point\"\n    )\n)\n"
+        );
+
+        let (pretty, errors) = recover("struct later;");
+        assert!(errors.is_empty());
+        assert_eq!(
+            pretty,
+            "Program(\n    StructDecl(\n        name = \"later\"\n    )\n)\n"
+        );
+    }
+
+    #[test]
+    fn token_stream_dump_and_access() {
+        let tokens = lexer("return \"hi\";".into());
+        assert_eq!(
+            tokens.to_string(),
+            "Keyword \"return\"\nStringLiteral \"\\\"hi\\\"\"\nSemicolon \";\"\n"
+        );
+        assert_eq!(tokens[2].kind, TokenKind::Semicolon);
+        assert!(!tokens.is_err());
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|token| token.kind).collect();
+        assert_eq!(kinds[0], TokenKind::Keyword);
+        assert!(lexer("return @;".into()).is_err());
+    }
+
+    #[test]
+    fn non_ascii_source_is_a_lex_error() {
+        let result = compile_to_asm("int main(void) { return 2; } \u{e9}", None);
+        match result {
+            Err(CompileError::Lex(errors)) => {
+                assert_eq!(errors, ["1:30: unexpected character '\u{e9}'"])
+            }
+            result => panic!("{result:?}"),
+        }
+    }
+
+    #[test]
+    fn every_unexpected_character_is_reported_where_it_is() {
+        match compile_to_asm("int main(void) {\n    return @ $;\n}\n", None) {
+            Err(CompileError::Lex(errors)) => assert_eq!(
+                errors,
+                [
+                    "2:12: unexpected character '@'",
+                    "2:14: unexpected character '$'"
+                ]
+            ),
+            result => panic!("{result:?}"),
+        }
+    }
+}
@@ -1,20 +1,149 @@
-use clap::{Args, Parser as ClapParser};
-use regex::Regex;
-use std::cell::Cell;
+use clap::{Args, Parser as ClapParser, Subcommand, ValueEnum};
+use std::cell::RefCell;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::{self, Command};
+use zcc::diagnostics::{self, ColorChoice, Warning};
+use zcc::format::{format_source, FormatError};
+use zcc::intern::Interner;
+use zcc::sarif::{self, Diagnostic};
+use zcc::sha256;
+use zcc::{
+    compile_source, explain_tokens, stack_map, token_stats, tree_to_dot, ASMProgram, CompileError,
+    CompileOptions, Stage, StageObserver, Standard, SyntaxTree, TokenStream, MAX_IDENTIFIER_LEN,
+    MAX_STACK, STACK_ALIGN,
+};
 
 #[derive(ClapParser)]
 #[command(version, about, long_about = None)]
 #[command(propagate_version = true)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Driver {
-    /// Path to the file to compile
-    path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// Paths to the files to compile
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
     #[command(flatten)]
     step: Step,
+    /// Dump the syntax tree in the given format and stop after parsing
+    #[arg(long, value_enum)]
+    dump_ast: Option<AstFormat>,
+    /// Kind of output to produce from the compiled files
+    #[arg(long, value_enum, default_value_t = Emit::Executable)]
+    emit: Emit,
+    /// Write the output to this path
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Archiver used to bundle objects for `--emit archive`
+    #[arg(long, default_value = "ar")]
+    ar: PathBuf,
+    /// When to color diagnostics; `auto` honors NO_COLOR and CLICOLOR_FORCE
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Same as `--color never`
+    #[arg(long, action, conflicts_with = "color")]
+    no_color: bool,
+    /// How to report errors and warnings on stderr
+    #[arg(long, value_enum, default_value_t = DiagnosticsFormat::Text)]
+    diagnostics_format: DiagnosticsFormat,
+    // With `--diagnostics-format sarif`, the diagnostics so far, for the log
+    // written on exit.
+    #[arg(skip)]
+    sarif: RefCell<Vec<Diagnostic>>,
+    /// Write the output of every compiler stage into this directory, in a
+    /// subdirectory per input when there are several
+    #[arg(long)]
+    trace_dir: Option<PathBuf>,
+    /// Only generate code for the function with this name, plus the globals
+    #[arg(long, value_name = "NAME")]
+    only: Option<String>,
+    /// Show which lexer rule matched each token and the input left after it
+    #[arg(long, action)]
+    explain_tokens: bool,
+    /// Print how many tokens of each kind the lexer produced
+    #[arg(long, action)]
+    token_stats: bool,
+    /// Print the size and SHA-256 of the assembly written for each file, to
+    /// check that builds are reproducible
+    #[arg(long, action)]
+    hash: bool,
+    /// Name the compiler and its version in the assembly
+    #[arg(long, action)]
+    emit_ident: bool,
+    /// Emit line-number debug info, and assemble with it
+    #[arg(short = 'g', action)]
+    debug_info: bool,
+    /// Longest identifier to accept, in bytes
+    #[arg(long, value_name = "BYTES", default_value_t = MAX_IDENTIFIER_LEN)]
+    max_identifier_length: usize,
+    /// Round stack frames up to a multiple of this many bytes, for ABIs other
+    /// than System V's
+    #[arg(long, value_name = "BYTES", default_value_t = STACK_ALIGN, value_parser = stack_align)]
+    stack_align: i64,
+    /// Refuse to generate a stack frame bigger than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = MAX_STACK)]
+    max_stack: i64,
+    /// Warn when main returns a constant the exit status can't hold
+    #[arg(long, action)]
+    warn_exit_code: bool,
+    /// Report warnings with these codes as errors, e.g. `W0001,W0002`
+    #[arg(long, value_name = "CODES", value_delimiter = ',')]
+    werror_codes: Vec<String>,
+    /// With `--parse`, print the partial syntax tree of a program with parse
+    /// errors, with an ErrorTree where parsing failed
+    #[arg(long, action, requires = "parse")]
+    recover: bool,
+    /// C standard to follow, gcc's `-std=` is accepted too
+    #[arg(long, value_enum, default_value_t = Standard::C17)]
+    std: Standard,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Print the file in canonical layout
+    Fmt {
+        /// Path to the file to format
+        path: PathBuf,
+        /// Print nothing and fail if the file isn't formatted already
+        #[arg(long, action)]
+        check: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Emit {
+    /// Assemble and link all files into one executable
+    Executable,
+    /// Assemble each file to an object and bundle them with `ar rcs`
+    Archive,
+    /// Stop after writing the assembly of each file
+    Asm,
+    /// Print the %rbp offset of each temporary instead of writing assembly
+    Stackmap,
 }
 
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum DiagnosticsFormat {
+    /// One line per diagnostic
+    Text,
+    /// A SARIF 2.1.0 log of all of them, written once zcc is done
+    Sarif,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AstFormat {
+    /// Graphviz digraph, render with `dot -Tsvg`
+    Dot,
+}
+
+// The stage flags are the ones the book's `test_compiler` script passes:
+// `--stage lex` runs `zcc --lex`, `parse` runs `--parse`, `validate` runs
+// `--validate`, `tacky` runs `--tacky`, `codegen` runs `--codegen`, and `run`
+// compiles with no flag and runs the executable next to the source. The
+// script only checks that valid programs exit with 0 and invalid ones with
+// anything else.
 #[derive(Args)]
 #[group(multiple = false)]
 struct Step {
@@ -24,565 +153,665 @@ struct Step {
     /// Run the lexer and parser, but stop before assembly generation
     #[arg(long, action)]
     parse: bool,
+    /// Run the lexer, parser and semantic checks, but stop before assembly generation
+    #[arg(long, action)]
+    validate: bool,
+    /// Run the lexer, parser and TACKY generation, but stop before assembly generation
+    #[arg(long, action)]
+    tacky: bool,
     /// Perform lexing, parsing, and assembly generation, but stop before code emission
     #[arg(long, action)]
     codegen: bool,
+    /// Check the program for errors without producing any output files, like gcc's
+    /// `-fsyntax-only` (which is accepted too)
+    #[arg(long, action)]
+    fsyntax_only: bool,
 }
 
+/// Exit codes of the driver, one per way a compilation can end.
 #[derive(Debug, PartialEq, Copy, Clone)]
-enum TokenKind {
-    Identifier,
-    Constant,
-    Keyword,
-    OpenParen,
-    CloseParen,
-    OpenBrace,
-    CloseBrace,
-    Semicolon,
-    Eof,
-    ErrorToken,
+enum ExitStatus {
+    /// Everything ran, or we stopped early at the stage that was asked for
+    Success = 0,
+    /// The source contains characters the lexer has no token for
+    LexError = 1,
+    /// The tokens don't form a valid program
+    ParseError = 2,
+    /// Assembly generation failed on a tree the parser accepted
+    CodegenError = 3,
+    /// Reading, writing or removing one of our files failed
+    IoError = 4,
+    /// gcc or ar could not be run or reported a failure
+    ToolchainError = 5,
+    /// A stage hit an invariant violation, which is a bug in zcc
+    InternalError = 6,
+    /// `fmt --check` found a file that isn't in canonical layout
+    Unformatted = 7,
+    /// The command line names something the program doesn't contain
+    UsageError = 8,
+    /// The program parses, but breaks a rule the semantic checks enforce
+    SemanticError = 9,
+}
+
+impl ExitStatus {
+    fn to_code(self) -> i32 {
+        self as i32
+    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-struct Token {
-    kind: TokenKind,
-    text: String,
+fn exit(status: ExitStatus) -> ! {
+    process::exit(status.to_code())
 }
 
-impl Token {
-    fn new(kind: TokenKind, text: &str) -> Self {
-        Token {
-            kind,
-            text: text.into(),
+impl From<&CompileError> for ExitStatus {
+    fn from(error: &CompileError) -> Self {
+        match error {
+            CompileError::Io(..) => ExitStatus::IoError,
+            CompileError::Lex(_) => ExitStatus::LexError,
+            CompileError::Parse(_) => ExitStatus::ParseError,
+            CompileError::Semantic(_) => ExitStatus::SemanticError,
+            CompileError::Codegen(_) => ExitStatus::CodegenError,
+            CompileError::Toolchain(_) => ExitStatus::ToolchainError,
+            CompileError::Internal(_) => ExitStatus::InternalError,
+            CompileError::Usage(_) => ExitStatus::UsageError,
         }
     }
-    fn open_paren() -> Self {
-        Self::new(TokenKind::OpenParen, "(")
-    }
-    fn close_paren() -> Self {
-        Self::new(TokenKind::CloseParen, ")")
-    }
-    fn open_brace() -> Self {
-        Self::new(TokenKind::OpenBrace, "{")
-    }
-    fn close_brace() -> Self {
-        Self::new(TokenKind::CloseBrace, "}")
-    }
-    fn semicolon() -> Self {
-        Self::new(TokenKind::Semicolon, ";")
-    }
-    fn constant(text: &str) -> Self {
-        Self::new(TokenKind::Constant, text)
-    }
-    fn keyword(text: &str) -> Self {
-        Self::new(TokenKind::Keyword, text)
-    }
-    fn identifier(text: &str) -> Self {
-        Self::new(TokenKind::Identifier, text)
-    }
-    fn error() -> Self {
-        Self::new(TokenKind::ErrorToken, "")
-    }
-    // fn eof() -> Self {
-    //     Self::new(TokenKind::Eof, "")
-    // }
 }
 
-fn lexer(text: String) -> Vec<Token> {
-    // while input isn't empty:
-    //   if input starts with whitespace:
-    //     trim whitespace from start of input
-    //   else:
-    //     find longest match at start of input for any regex in Table 1-1
-    //     if no match is found, raise an error
-    //     convert matching substring into a token
-    //     remove matching substring from start of input
-    let mut token = vec![];
-    let mut input = text.as_str();
-    while !input.is_empty() {
-        let char = input.chars().next().expect("Should have had a character");
-
-        if char.is_whitespace() {
-        } else if char == '(' {
-            token.push(Token::open_paren());
-        } else if char == ')' {
-            token.push(Token::close_paren());
-        } else if char == '{' {
-            token.push(Token::open_brace());
-        } else if char == '}' {
-            token.push(Token::close_brace());
-        } else if char == ';' {
-            token.push(Token::semicolon());
-        } else {
-            let keyword = Regex::new(r"^(void|int|return)\b").unwrap();
-            let constant = Regex::new(r"^([0-9]+)\b").unwrap();
-            let identifier = Regex::new(r"^([a-zA-Z_]\w*)\b").unwrap();
-            if constant.is_match(input) {
-                let caps = constant.captures(input).unwrap();
-                let matched_const = caps.get(0).unwrap().as_str();
-                input = &input[matched_const.len()..];
-                token.push(Token::constant(matched_const));
-                continue;
-            } else if identifier.is_match(input) {
-                if keyword.is_match(input) {
-                    let caps = keyword.captures(input).unwrap();
-                    let matched_keyword = caps.get(0).unwrap().as_str();
-                    input = &input[matched_keyword.len()..];
-                    token.push(Token::keyword(matched_keyword));
-                    continue;
-                }
-                let caps = identifier.captures(input).unwrap();
-                let matched_identifier = caps.get(0).unwrap().as_str();
-                input = &input[matched_identifier.len()..];
-                token.push(Token::identifier(matched_identifier));
-                continue;
-            } else {
-                token.push(Token::error())
+// Print the error the way its kind is usually reported and exit with its
+// status.
+fn fail(cli: &Driver, error: CompileError) -> ! {
+    if cli.diagnostics_format == DiagnosticsFormat::Sarif {
+        cli.sarif.borrow_mut().extend(Diagnostic::errors(&error));
+        eprint!("{}", sarif::log(&cli.sarif.borrow()));
+        exit(ExitStatus::from(&error));
+    }
+    let color = color(cli);
+    match &error {
+        CompileError::Lex(_)
+        | CompileError::Parse(_)
+        | CompileError::Semantic(_)
+        | CompileError::Codegen(_) => {
+            for message in error.messages() {
+                eprintln!("{}", diagnostics::error(&message, color));
             }
         }
-
-        input = &input[1..];
+        CompileError::Io(..)
+        | CompileError::Toolchain(_)
+        | CompileError::Internal(_)
+        | CompileError::Usage(_) => eprintln!("{error}"),
     }
-    // token.push(Token::eof());
-    token
+    exit(ExitStatus::from(&error))
 }
 
-#[derive(Debug, PartialEq, Clone)]
-enum TreeKind {
-    Program,
-    Function,
-    Return,
-    ErrorTree,
-}
-#[derive(Debug, PartialEq, Clone)]
-struct Tree {
-    kind: TreeKind,
-    children: Vec<Child>,
-}
-#[derive(Debug, PartialEq, Clone)]
-enum Child {
-    Token(Token),
-    Tree(Tree),
+// Exit with success, after writing the SARIF log of the warnings if asked to.
+fn succeed(cli: &Driver) -> ! {
+    if cli.diagnostics_format == DiagnosticsFormat::Sarif {
+        eprint!("{}", sarif::log(&cli.sarif.borrow()));
+    }
+    exit(ExitStatus::Success)
 }
 
-#[derive(Debug, PartialEq)]
-enum Event {
-    Open { kind: TreeKind },
-    Close,
-    Advance,
+fn color(cli: &Driver) -> bool {
+    diagnostics::use_color(if cli.no_color {
+        ColorChoice::Never
+    } else {
+        cli.color
+    })
 }
-struct MarkOpened {
-    index: usize,
+
+fn run_tool(command: &mut Command) -> Result<(), CompileError> {
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(CompileError::Toolchain(format!(
+            "{:?} failed with: {status}",
+            command.get_program()
+        ))),
+        Err(e) => Err(CompileError::Toolchain(format!(
+            "could not run {:?}: {e}",
+            command.get_program()
+        ))),
+    }
 }
-struct Parser {
-    tokens: Vec<Token>,
-    pos: usize,
-    fuel: Cell<u32>,
-    events: Vec<Event>,
+
+// Writes each stage's output to its own file in the trace directory.
+struct TraceDir {
+    dir: PathBuf,
+    // The first file that couldn't be written. The stages after it still
+    // run, and the compile fails with it once they're done.
+    error: Option<CompileError>,
 }
 
-impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
-        Parser {
-            tokens,
-            pos: 0,
-            fuel: Cell::new(256),
-            events: Vec::default(),
+impl TraceDir {
+    fn create(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        // Don't leave files of a previous run behind for stages we won't reach.
+        for stage in Stage::ALL {
+            let _ = fs::remove_file(dir.join(stage.file_name()));
         }
+        Ok(TraceDir { dir, error: None })
     }
+}
 
-    fn open(&mut self) -> MarkOpened {
-        let mark = MarkOpened {
-            index: self.events.len(),
-        };
-        self.events.push(Event::Open {
-            kind: TreeKind::ErrorTree,
-        });
-        mark
-    }
-    fn advance(&mut self) {
-        assert!(!self.eof());
-        self.fuel.set(256);
-        self.events.push(Event::Advance);
-        self.pos += 1;
+impl StageObserver for TraceDir {
+    fn observe(&mut self, stage: Stage, output: &str) {
+        if self.error.is_some() {
+            return;
+        }
+        let file = self.dir.join(stage.file_name());
+        if let Err(e) = fs::write(&file, output) {
+            self.error = Some(CompileError::Io(file, e));
+        }
     }
+}
 
-    fn eof(&self) -> bool {
-        self.pos == self.tokens.len()
-    }
-    fn close(&mut self, m: MarkOpened, kind: TreeKind) {
-        self.events[m.index] = Event::Open { kind };
-        self.events.push(Event::Close);
+// Like println!, except when stdout carries a syntax tree dump or stack map
+// for another program to read, or -fsyntax-only asked for nothing but
+// diagnostics.
+macro_rules! progress {
+    ($cli:expr, $($arg:tt)*) => {
+        if $cli.dump_ast.is_none()
+            && !$cli.step.fsyntax_only
+            && !matches!($cli.emit, Emit::Stackmap)
+        {
+            println!($($arg)*);
+        }
+    };
+}
+
+// Preprocess the file at `path` and compile it to assembly. Gives the path of
+// the assembly file, or None when the flags say to stop before writing it.
+fn compile(cli: &Driver, path: &Path) -> Result<Option<PathBuf>, CompileError> {
+    progress!(cli, "Starting to compile {}", path.display());
+
+    let trace = cli
+        .trace_dir
+        .as_ref()
+        .map(|dir| {
+            let dir = if cli.paths.len() > 1 {
+                dir.join(path.file_stem().unwrap_or_default())
+            } else {
+                dir.clone()
+            };
+            TraceDir::create(dir.clone()).map_err(|e| CompileError::Io(dir, e))
+        })
+        .transpose()?;
+
+    progress!(cli, "Preprocessing");
+    let input_file = path;
+    let prep_file = &path.with_extension("i");
+    progress!(
+        cli,
+        "gcc -E {} -o {}",
+        input_file.display(),
+        prep_file.display()
+    );
+    run_tool(
+        Command::new("gcc")
+            .arg("-E")
+            .arg(input_file)
+            .arg("-o")
+            .arg(prep_file),
+    )?;
+    progress!(cli, "Preprocess finished");
+
+    let result = compile_preprocessed(cli, path, prep_file, trace);
+    // Don't let a failure to clean up hide why the compilation failed.
+    let removed = fs::remove_file(prep_file).map_err(|e| CompileError::Io(prep_file.clone(), e));
+    let ass_file = result?;
+    removed?;
+    Ok(ass_file)
+}
+
+// What the driver shows of each stage, as its flags ask for it.
+struct Shown<'a> {
+    cli: &'a Driver,
+    text: &'a str,
+    trace: Option<TraceDir>,
+}
+
+impl StageObserver for Shown<'_> {
+    fn observe(&mut self, stage: Stage, output: &str) {
+        // The dumps are for looking at a stage, a full compile prints neither.
+        match stage {
+            Stage::Tokens if self.cli.step.lex => print!("{output}"),
+            Stage::Cst if self.cli.step.parse && self.cli.dump_ast.is_none() => print!("{output}"),
+            _ => {}
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.observe(stage, output);
+        }
     }
 
-    fn nth(&self, lookahead: usize) -> TokenKind {
-        if self.fuel.get() == 0 {
-            panic!("parser is stuck")
+    fn tokens(&mut self, tokens: &TokenStream) {
+        if self.cli.explain_tokens {
+            print!("{}", explain_tokens(self.text, tokens));
+        }
+        if self.cli.token_stats {
+            for (kind, count) in token_stats(tokens.as_slice()) {
+                println!(
+                    "{:<12} {count:>5} {}",
+                    format!("{kind:?}"),
+                    "#".repeat(count)
+                );
+            }
         }
-        self.fuel.set(self.fuel.get() - 1);
-        self.tokens
-            .get(self.pos + lookahead)
-            .map_or(TokenKind::Eof, |t| t.kind)
     }
 
-    fn at(&self, kind: TokenKind) -> bool {
-        self.nth(0) == kind
+    fn tree(&mut self, tree: &SyntaxTree) {
+        if let Some(AstFormat::Dot) = self.cli.dump_ast {
+            print!("{}", tree_to_dot(tree));
+        }
     }
 
-    fn eat(&mut self, kind: TokenKind) -> bool {
-        if self.at(kind) {
-            self.advance();
-            true
-        } else {
-            false
+    fn asm(&mut self, asm: &ASMProgram, interner: &Interner) {
+        if let Emit::Stackmap = self.cli.emit {
+            print!("{}", stack_map(asm, interner));
         }
     }
 
-    fn expect(&mut self, kind: TokenKind) {
-        if self.eat(kind) {
+    fn warning(&mut self, warning: &Warning) {
+        if self.cli.diagnostics_format == DiagnosticsFormat::Sarif {
+            self.cli
+                .sarif
+                .borrow_mut()
+                .push(Diagnostic::warning(warning));
             return;
         }
-        eprintln!("expected {kind:?}");
-        process::exit(1);
-    }
-
-    fn build_tree(self) -> Tree {
-        let mut tokens = self.tokens.into_iter();
-        let mut events = self.events;
-        let mut stack = Vec::new();
-
-        assert!(matches!(events.pop(), Some(Event::Close)));
-
-        for event in events {
-            match event {
-                Event::Open { kind } => stack.push(Tree {
-                    kind,
-                    children: Vec::new(),
-                }),
-                Event::Close => {
-                    let tree = stack.pop().unwrap();
-                    stack.last_mut().unwrap().children.push(Child::Tree(tree));
-                }
-                Event::Advance => {
-                    let token = tokens.next().unwrap();
-                    stack.last_mut().unwrap().children.push(Child::Token(token))
-                }
-            }
-        }
-
-        assert!(stack.len() == 1);
-        assert!(tokens.next().is_none());
+        let message = format!("{} [{}]", warning.message, warning.code);
+        eprintln!("{}", diagnostics::warning(&message, color(self.cli)));
+    }
+}
 
-        stack.pop().unwrap()
+// The library's options for the flags. Every flag that stops early stops
+// after one of the stages.
+fn options(cli: &Driver) -> CompileOptions {
+    let step = &cli.step;
+    let last_stage = if step.lex {
+        Stage::Tokens
+    } else if step.parse || step.validate || step.fsyntax_only || cli.dump_ast.is_some() {
+        Stage::Cst
+    } else if step.tacky {
+        Stage::Tacky
+    } else if step.codegen || matches!(cli.emit, Emit::Stackmap) {
+        Stage::AsmIr
+    } else {
+        Stage::Assembly
+    };
+    CompileOptions {
+        standard: cli.std,
+        max_identifier_len: cli.max_identifier_length,
+        last_stage,
+        checks: !step.parse,
+        warn_exit_code: cli.warn_exit_code,
+        werror_codes: cli.werror_codes.clone(),
+        recover: cli.recover,
+        only: cli.only.clone(),
+        emit_ident: cli.emit_ident,
+        stack_align: cli.stack_align,
+        max_stack: cli.max_stack,
+        debug_info: cli.debug_info,
     }
+}
 
-    fn pretty_print(tree: &Tree, depth: usize, show_kind: bool) {
-        //  Program(
-        //      Function(
-        //          name="main",
-        //          body=Return(
-        //              Constant(2)
-        //          )
-        //      )
-        //  )
-        if show_kind {
-            println!("{:depth$}{:?}(", "", tree.kind);
-        }
-        match tree.kind {
-            TreeKind::Program => {
-                for child in &tree.children {
-                    if let Child::Tree(t) = child {
-                        Parser::pretty_print(t, depth + 4, true);
-                    }
-                }
+fn compile_preprocessed(
+    cli: &Driver,
+    path: &Path,
+    prep_file: &Path,
+    trace: Option<TraceDir>,
+) -> Result<Option<PathBuf>, CompileError> {
+    progress!(cli, "Lexing!");
+    let text = fs::read_to_string(prep_file).map_err(|e| CompileError::Io(prep_file.into(), e))?;
+    let mut shown = Shown {
+        cli,
+        text: &text,
+        trace,
+    };
+    let result = compile_source(&text, &options(cli), Some(&mut shown));
+    // An error in the source says more than a trace file that couldn't be
+    // written, so it goes first.
+    let trace_error = shown.trace.and_then(|trace| trace.error);
+    let output = result.map_err(|error| {
+        // Name the file for the errors that aren't about a line of it.
+        match error {
+            CompileError::Internal(message) => {
+                CompileError::Internal(format!("{}: {message}", path.display()))
             }
-            TreeKind::Function => {
-                if let Some(Child::Token(Token {
-                    text,
-                    kind: TokenKind::Identifier,
-                })) = tree.children.get(1)
-                {
-                    println!("{:depth$}name = \"{text}\"", "", depth = depth + 4);
-                }
-                if let Some(Child::Tree(Tree { kind, children })) = tree.children.get(6) {
-                    println!("{:depth$}body = {kind:?}(", "", depth = depth + 4);
-                    Parser::pretty_print(
-                        &Tree {
-                            kind: kind.clone(),
-                            children: children.clone(),
-                        },
-                        depth + 4,
-                        false,
-                    );
-                    println!("{:depth$})", "", depth = depth + 4);
-                }
+            CompileError::Usage(message) => {
+                CompileError::Usage(format!("{}: {message}", path.display()))
             }
-            TreeKind::Return => {
-                if let Some(Child::Token(Token { text, kind })) = tree.children.get(1) {
-                    println!("{:depth$}{kind:?}({text})", "", depth = depth + 4);
-                }
-            }
-            TreeKind::ErrorTree => {}
-        }
-        if show_kind {
-            println!("{:depth$})", "");
+            error => error,
         }
+    })?;
+    if let Some(error) = trace_error {
+        return Err(error);
     }
-}
 
-fn parse_program(p: &mut Parser) {
-    let m = p.open();
-
-    dbg!(p.tokens.len());
+    let step = &cli.step;
+    if step.fsyntax_only || cli.dump_ast.is_some() {
+        return Ok(None);
+    }
+    if step.lex {
+        println!("Wrapping it up after Lexing.");
+        return Ok(None);
+    }
+    if step.parse || step.validate {
+        println!("Wrapping it up after Parsing.");
+        return Ok(None);
+    }
+    if step.tacky {
+        println!("Wrapping it up after TACKY generation.");
+        return Ok(None);
+    }
+    if step.codegen {
+        println!("Wrapping it up after Code generation.");
+        return Ok(None);
+    }
+    if let Emit::Stackmap = cli.emit {
+        return Ok(None);
+    }
 
-    while !p.eof() {
-        dbg!(p.nth(0));
-        dbg!(p.pos);
-        if p.at(TokenKind::Keyword) {
-            parse_function(p)
-        } else {
-            panic!("expected a keyword");
-        }
+    let ass_file = path.with_extension("s");
+    let assembly = output.assembly.into_bytes();
+    if cli.hash {
+        println!(
+            "{}: {} bytes, sha256 {}",
+            ass_file.display(),
+            assembly.len(),
+            sha256::hex(&sha256::sha256(&assembly))
+        );
     }
-    p.close(m, TreeKind::Program);
-    //    Tree {
-    //        kind: TreeKind::Program,
-    //        children: vec![Child::Tree(Tree {
-    //            kind: TreeKind::Function,
-    //            children: vec![Child::Tree(Tree {
-    //                kind: TreeKind::Return,
-    //                children: vec![Child::Token(Token::Constant(2))],
-    //            })],
-    //        })],
-    //    }
-}
+    fs::write(&ass_file, assembly).map_err(|e| CompileError::Io(ass_file.clone(), e))?;
 
-// function = "int" <identifier> "(" "void" ")" "{" <statement> "}"
-fn parse_function(p: &mut Parser) {
-    // TODO: is this enough as a guard? or do we need lookahead later?
-    assert!(p.at(TokenKind::Keyword));
-    let m = p.open();
-
-    p.expect(TokenKind::Keyword);
-    p.expect(TokenKind::Identifier);
-    p.expect(TokenKind::OpenParen);
-    p.expect(TokenKind::Keyword);
-    p.expect(TokenKind::CloseParen);
-    p.expect(TokenKind::OpenBrace);
-    parse_statement(p);
-    p.expect(TokenKind::CloseBrace);
-
-    p.close(m, TreeKind::Function);
+    Ok(Some(ass_file))
 }
 
-// "return" <exp> ";"
-fn parse_statement(p: &mut Parser) {
-    let m = p.open();
-    p.expect(TokenKind::Keyword);
-    p.expect(TokenKind::Constant);
-    p.expect(TokenKind::Semicolon);
-
-    p.close(m, TreeKind::Return);
+// `--stack-align` only makes sense as a power of two.
+fn stack_align(arg: &str) -> Result<i64, String> {
+    match arg.parse::<i64>() {
+        Ok(align) if align > 0 && align.count_ones() == 1 => Ok(align),
+        _ => Err("expected a power of two".into()),
+    }
 }
 
-// program = Program(function_definition)
-// function_definition = Function(identifier name, instruction* instructions)
-// instruction = Mov(operand src, operand dst) | Ret
-// operand = Imm(int) | Register
-
-#[derive(Debug, PartialEq, Clone)]
-struct ASMProgram(ASMFunction);
-#[derive(Debug, PartialEq, Clone)]
-struct ASMFunction {
-    identifier: String,
-    instructions: Vec<ASMInstruction>,
-}
-#[derive(Debug, PartialEq, Clone)]
-enum ASMInstruction {
-    Mov { src: ASMOperand, dst: ASMOperand },
-    Ret,
+fn format(path: &Path, check: bool) -> ExitStatus {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Failed to read input file: {e}");
+            return ExitStatus::IoError;
+        }
+    };
+    let formatted = match format_source(&text) {
+        Ok(formatted) => formatted,
+        Err(FormatError::Lex) => {
+            eprintln!("refusing to format {}: it doesn't lex", path.display());
+            return ExitStatus::LexError;
+        }
+        Err(FormatError::Comments) => {
+            eprintln!(
+                "refusing to format {}: its comments would be lost",
+                path.display()
+            );
+            return ExitStatus::LexError;
+        }
+        Err(FormatError::Parse(_)) => {
+            eprintln!("refusing to format {}: it doesn't parse", path.display());
+            return ExitStatus::ParseError;
+        }
+    };
+    if !check {
+        print!("{formatted}");
+    } else if formatted != text {
+        eprintln!("{} is not formatted", path.display());
+        return ExitStatus::Unformatted;
+    }
+    ExitStatus::Success
 }
-#[derive(Debug, PartialEq, Clone)]
-enum ASMOperand {
-    Imm(u32),
-    Register,
+
+fn archive_command(ar: &Path, archive: &Path, objects: &[PathBuf]) -> Command {
+    let mut command = Command::new(ar);
+    command.arg("rcs").arg(archive).args(objects);
+    command
 }
 
-fn generate_assembly(tree: &Tree) -> ASMProgram {
-    match tree.kind {
-        TreeKind::Program => {
-            if let Some(Child::Tree(tree)) = tree.children.first() {
-                ASMProgram(generate_function(tree))
-            } else {
-                panic!("Should have had a Tree Child");
-            }
+// gcc-style `@file` arguments: the whitespace-separated contents of the file
+// are spliced in its place and may name further response files.
+fn expand_response_files(args: Vec<String>) -> Result<Vec<String>, String> {
+    fn expand(
+        arg: String,
+        open: &mut Vec<PathBuf>,
+        expanded: &mut Vec<String>,
+    ) -> Result<(), String> {
+        let Some(file) = arg.strip_prefix('@') else {
+            expanded.push(arg);
+            return Ok(());
+        };
+        let path = PathBuf::from(file);
+        if open.contains(&path) {
+            return Err(format!("response file {} includes itself", path.display()));
         }
-        _ => panic!("should have been a program here."),
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("could not read response file {}: {e}", path.display()))?;
+        open.push(path);
+        for arg in contents.split_whitespace() {
+            expand(arg.into(), open, expanded)?;
+        }
+        open.pop();
+        Ok(())
     }
+
+    let mut expanded = vec![];
+    for arg in args {
+        expand(arg, &mut vec![], &mut expanded)?;
+    }
+    Ok(expanded)
 }
 
-fn generate_function(tree: &Tree) -> ASMFunction {
-    match tree.kind {
-        TreeKind::Function => {
-            if let Some(Child::Token(Token {
-                text,
-                kind: TokenKind::Identifier,
-            })) = tree.children.get(1)
-            {
-                if let Some(Child::Tree(tree)) = tree.children.get(6) {
-                    ASMFunction {
-                        identifier: text.to_owned(),
-                        instructions: generate_return(tree),
-                    }
-                } else {
-                    panic!("could not find body");
-                }
-            } else {
-                panic!("could not find identifier");
-            }
+fn main() {
+    let args = match expand_response_files(std::env::args().collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            exit(ExitStatus::IoError);
         }
-        _ => panic!("should have been a function."),
+    };
+    let cli = Driver::parse_from(args.into_iter().map(|arg| {
+        // clap has no single-dash long flags, so spell gcc's the clap way.
+        if arg == "-fsyntax-only" || arg.starts_with("-std=") {
+            format!("-{arg}")
+        } else {
+            arg
+        }
+    }));
+
+    if let Some(Commands::Fmt { path, check }) = &cli.command {
+        exit(format(path, *check));
     }
-}
 
-fn generate_return(tree: &Tree) -> Vec<ASMInstruction> {
-    match tree.kind {
-        TreeKind::Return => {
-            if let Some(Child::Token(Token {
-                text,
-                kind: TokenKind::Constant,
-            })) = tree.children.get(1)
-            {
-                vec![
-                    ASMInstruction::Mov {
-                        src: ASMOperand::Imm(text.parse().unwrap()),
-                        dst: ASMOperand::Register,
-                    },
-                    ASMInstruction::Ret,
-                ]
-            } else {
-                panic!("No constant found where one was expected");
-            }
+    let mut ass_files = vec![];
+    for path in &cli.paths {
+        match compile(&cli, path) {
+            Ok(Some(ass_file)) => ass_files.push(ass_file),
+            Ok(None) => {}
+            Err(error) => fail(&cli, error),
         }
-        _ => panic!("should have been a function."),
     }
-}
+    if ass_files.len() != cli.paths.len() {
+        succeed(&cli);
+    }
 
-fn emit_program(asm: &ASMProgram) -> Vec<u8> {
-    let mut output = vec![];
-
-    let ASMProgram(f) = asm;
-    let ASMFunction {
-        identifier,
-        instructions,
-    } = f;
-    output.extend_from_slice(b"\t.globl\t_");
-    output.extend_from_slice(identifier.as_bytes());
-    output.extend_from_slice(b"\n");
-    output.extend_from_slice(b"_");
-    output.extend_from_slice(identifier.as_bytes());
-    output.extend_from_slice(b":\n");
-    for instruction in instructions {
-        match instruction {
-            ASMInstruction::Mov { src, dst } => {
-                output.extend_from_slice(b"\tmovl\t");
-                output.extend(emit_op(src));
-                output.extend_from_slice(b", ");
-                output.extend(emit_op(dst));
-                output.extend_from_slice(b"\n");
+    match cli.emit {
+        Emit::Asm | Emit::Stackmap => {}
+        Emit::Executable => {
+            let out_file = &cli
+                .output
+                .clone()
+                .unwrap_or_else(|| cli.paths[0].with_extension(""));
+            println!(
+                "gcc {}{} -o {}",
+                debug_flag(&cli),
+                display_paths(&ass_files),
+                out_file.display()
+            );
+            let mut gcc = Command::new("gcc");
+            if cli.debug_info {
+                gcc.arg("-g");
             }
-            ASMInstruction::Ret => {
-                output.extend_from_slice(b"\tret\n");
+            if let Err(error) = run_tool(gcc.args(&ass_files).arg("-o").arg(out_file)) {
+                fail(&cli, error);
             }
+            println!("Assemble finished");
         }
-    }
-    output
-}
+        Emit::Archive => {
+            let mut obj_files = vec![];
+            for ass_file in &ass_files {
+                let obj_file = ass_file.with_extension("o");
+                println!(
+                    "gcc {}-c {} -o {}",
+                    debug_flag(&cli),
+                    ass_file.display(),
+                    obj_file.display()
+                );
+                let mut gcc = Command::new("gcc");
+                if cli.debug_info {
+                    gcc.arg("-g");
+                }
+                let assembled = run_tool(gcc.arg("-c").arg(ass_file).arg("-o").arg(&obj_file));
+                if let Err(error) = assembled {
+                    fail(&cli, error);
+                }
+                println!("Assemble finished");
+                obj_files.push(obj_file);
+            }
 
-fn emit_op(op: &ASMOperand) -> Vec<u8> {
-    let mut output = vec![];
-    match op {
-        ASMOperand::Imm(i) => {
-            output.extend_from_slice(b"$");
-            output.extend_from_slice(i.to_string().as_bytes());
-        }
-        ASMOperand::Register => {
-            output.extend_from_slice(b"%eax");
+            let archive = &cli
+                .output
+                .clone()
+                .unwrap_or_else(|| cli.paths[0].with_extension("a"));
+            println!(
+                "{} rcs {} {}",
+                cli.ar.display(),
+                archive.display(),
+                display_paths(&obj_files)
+            );
+            if let Err(error) = run_tool(&mut archive_command(&cli.ar, archive, &obj_files)) {
+                fail(&cli, error);
+            }
+            println!("Archive finished");
         }
     }
-    output
+    succeed(&cli);
 }
 
-fn main() {
-    let cli = Driver::parse();
-    println!("Starting to compile {}", cli.path.display());
-
-    println!("Preprocessing");
-    let input_file = &cli.path;
-    let prep_file = &cli.path.with_extension("i");
-    println!(
-        "gcc -E -P {} -o {}",
-        input_file.display(),
-        prep_file.display()
-    );
-    let prep = Command::new("gcc")
-        .arg("-E")
-        .arg("-P")
-        .arg(input_file)
-        .arg("-o")
-        .arg(prep_file)
-        .status()
-        .unwrap();
-    println!("Preprocess finished with: {prep}");
-
-    println!("Lexing!");
-    let text = fs::read_to_string(prep_file).expect("Failed to read input file.");
-    let tokens = lexer(text);
-
-    dbg!(&tokens);
-    if cli.step.lex {
-        println!("Wrapping it up after Lexing.");
-        fs::remove_file(prep_file).expect("Could not remove preprocessed file.");
-        if tokens.iter().any(|t| t.kind == TokenKind::ErrorToken) {
-            process::exit(1);
-        }
-        process::exit(0);
+// `-g ` when the assembler is asked for debug info too, for the echoed
+// commands.
+fn debug_flag(cli: &Driver) -> &'static str {
+    if cli.debug_info {
+        "-g "
+    } else {
+        ""
     }
+}
 
-    let mut parser = Parser::new(tokens);
-    parse_program(&mut parser);
-    dbg!(&parser.events);
-    let tree = parser.build_tree();
-    //dbg!(&tree);
-    dbg!(Parser::pretty_print(&tree, 0, true));
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    if cli.step.parse {
-        println!("Wrapping it up after Parsing.");
-        fs::remove_file(prep_file).expect("Could not remove preprocessed file.");
-        process::exit(0);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_command_bundles_the_objects() {
+        let objects = [PathBuf::from("a.o"), PathBuf::from("b.o")];
+        let command = archive_command(Path::new("llvm-ar"), Path::new("lib.a"), &objects);
+        assert_eq!(command.get_program(), "llvm-ar");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["rcs", "lib.a", "a.o", "b.o"]
+        );
     }
 
-    let asm_tree = generate_assembly(&tree);
-    dbg!(&asm_tree);
-
-    if cli.step.codegen {
-        println!("Wrapping it up after Code generation.");
-        fs::remove_file(prep_file).expect("Could not remove preprocessed file.");
-        process::exit(0);
+    #[test]
+    fn exit_status_codes() {
+        let codes = [
+            (ExitStatus::Success, 0),
+            (ExitStatus::LexError, 1),
+            (ExitStatus::ParseError, 2),
+            (ExitStatus::CodegenError, 3),
+            (ExitStatus::IoError, 4),
+            (ExitStatus::ToolchainError, 5),
+            (ExitStatus::InternalError, 6),
+            (ExitStatus::Unformatted, 7),
+            (ExitStatus::UsageError, 8),
+            (ExitStatus::SemanticError, 9),
+        ];
+        for (status, code) in codes {
+            assert_eq!(status.to_code(), code, "{status:?}");
+        }
+        let error = CompileError::Semantic(vec![]);
+        assert_eq!(ExitStatus::from(&error), ExitStatus::SemanticError);
+        let error = CompileError::Toolchain(String::new());
+        assert_eq!(ExitStatus::from(&error), ExitStatus::ToolchainError);
+        let error = CompileError::Internal(String::new());
+        assert_eq!(ExitStatus::from(&error), ExitStatus::InternalError);
+        let error = CompileError::Usage(String::new());
+        assert_eq!(ExitStatus::from(&error), ExitStatus::UsageError);
     }
 
-    let ass_file = &cli.path.with_extension("s");
-    fs::write(ass_file, emit_program(&asm_tree)).unwrap();
+    #[test]
+    fn response_files_are_expanded() {
+        let dir = std::env::temp_dir().join(format!("zcc-response-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (args, nested, cycle) = (dir.join("args"), dir.join("nested"), dir.join("cycle"));
+        fs::write(&args, "--parse\n  foo.c\n").unwrap();
+        fs::write(&nested, format!("--lex @{}", args.display())).unwrap();
+        fs::write(&cycle, format!("@{}", cycle.display())).unwrap();
+        let expand =
+            |file: &Path| expand_response_files(vec!["zcc".into(), format!("@{}", file.display())]);
+
+        assert_eq!(expand(&args).unwrap(), ["zcc", "--parse", "foo.c"]);
+        assert_eq!(
+            expand(&nested).unwrap(),
+            ["zcc", "--lex", "--parse", "foo.c"]
+        );
+        assert!(expand(&cycle).unwrap_err().contains("includes itself"));
+        assert!(expand(&dir.join("missing")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-    fs::remove_file(prep_file).expect("Could not remove preprocessed file.");
+    #[test]
+    fn flags_become_compile_options() {
+        let cli =
+            Driver::try_parse_from(["zcc", "--werror-codes", "W0003,W0004", "--parse", "a.c"])
+                .unwrap();
+        let parse = options(&cli);
+        assert_eq!(parse.werror_codes, ["W0003", "W0004"]);
+        assert_eq!(parse.last_stage, Stage::Cst);
+        assert!(!parse.checks);
+
+        let cli = Driver::try_parse_from(["zcc", "--validate", "a.c"]).unwrap();
+        assert_eq!(options(&cli).last_stage, Stage::Cst);
+        assert!(options(&cli).checks);
+        let cli = Driver::try_parse_from(["zcc", "--codegen", "a.c"]).unwrap();
+        assert_eq!(options(&cli).last_stage, Stage::AsmIr);
+        let cli = Driver::try_parse_from(["zcc", "a.c"]).unwrap();
+        assert_eq!(options(&cli), CompileOptions::default());
+        let cli = Driver::try_parse_from(["zcc", "--stack-align", "32", "a.c"]).unwrap();
+        assert_eq!(options(&cli).stack_align, 32);
+        assert!(Driver::try_parse_from(["zcc", "--stack-align", "12", "a.c"]).is_err());
+    }
 
-    let out_file = &cli.path.with_extension("");
-    println!("gcc {} -o {}", ass_file.display(), out_file.display());
-    let assemble = Command::new("gcc")
-        .arg(ass_file)
-        .arg("-o")
-        .arg(out_file)
-        .status()
-        .unwrap();
-    println!("Preprocess finished with: {assemble}");
+    #[test]
+    fn several_inputs_and_emit_archive() {
+        let cli = Driver::try_parse_from(["zcc", "--emit", "archive", "a.c", "b.c"]).unwrap();
+        assert_eq!(cli.paths, [PathBuf::from("a.c"), PathBuf::from("b.c")]);
+        assert!(matches!(cli.emit, Emit::Archive));
+        assert_eq!(cli.ar, PathBuf::from("ar"));
+    }
 }
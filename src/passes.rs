@@ -0,0 +1,274 @@
+// Checks and rewrites that run on the syntax tree between parsing and code
+// generation. The compile options, and so the driver's flags, decide which
+// passes go into the registry, and they run in the order they were
+// registered.
+
+use crate::diagnostics::Warning;
+use crate::{
+    check_constants, check_prototypes, check_returns, exit_code_warnings, missing_return_warnings,
+    unreachable_code_warnings, CompileOptions, SyntaxTree,
+};
+
+// What a pass gets to look at and change.
+pub struct CompilationUnit {
+    pub tree: SyntaxTree,
+    pub warnings: Vec<Warning>,
+}
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+    // An error stops the compilation; passes after this one don't run.
+    fn run(&self, unit: &mut CompilationUnit) -> Result<(), String>;
+}
+
+#[derive(Default)]
+pub struct PassRegistry {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassRegistry {
+    // The passes `options` select. Only `--parse` turns the checks off.
+    pub fn from_options(options: &CompileOptions) -> Self {
+        let mut passes = PassRegistry::default();
+        if options.checks {
+            passes.register_checks();
+        }
+        passes.register(Box::new(UnreachableCodeCheck));
+        if options.warn_exit_code {
+            passes.register(Box::new(ExitCodeCheck));
+        }
+        passes
+    }
+
+    pub fn register(&mut self, pass: Box<dyn Pass>) {
+        self.passes.push(pass);
+    }
+
+    // The checks a program has to pass before code generation can run on it.
+    pub fn register_checks(&mut self) {
+        self.register(Box::new(PrototypeCheck));
+        self.register(Box::new(ReturnCheck));
+        self.register(Box::new(MissingReturnCheck));
+        self.register(Box::new(ConstantCheck));
+    }
+
+    pub fn run(&self, unit: &mut CompilationUnit) -> Result<(), String> {
+        for pass in &self.passes {
+            pass.run(unit)?;
+        }
+        Ok(())
+    }
+}
+
+// Warn when main returns a constant the exit status can't hold.
+pub struct ExitCodeCheck;
+
+impl Pass for ExitCodeCheck {
+    fn name(&self) -> &'static str {
+        "exit-code-check"
+    }
+
+    fn run(&self, unit: &mut CompilationUnit) -> Result<(), String> {
+        unit.warnings.extend(exit_code_warnings(&unit.tree));
+        Ok(())
+    }
+}
+
+// Warn about statements after a return.
+pub struct UnreachableCodeCheck;
+
+impl Pass for UnreachableCodeCheck {
+    fn name(&self) -> &'static str {
+        "unreachable-code-check"
+    }
+
+    fn run(&self, unit: &mut CompilationUnit) -> Result<(), String> {
+        unit.warnings.extend(unreachable_code_warnings(&unit.tree));
+        Ok(())
+    }
+}
+
+// Prototypes have to agree with their function and with each other.
+pub struct PrototypeCheck;
+
+impl Pass for PrototypeCheck {
+    fn name(&self) -> &'static str {
+        "prototype-check"
+    }
+
+    fn run(&self, unit: &mut CompilationUnit) -> Result<(), String> {
+        check_prototypes(&unit.tree)
+    }
+}
+
+// Returns have a value exactly when their function isn't void.
+pub struct ReturnCheck;
+
+impl Pass for ReturnCheck {
+    fn name(&self) -> &'static str {
+        "return-check"
+    }
+
+    fn run(&self, unit: &mut CompilationUnit) -> Result<(), String> {
+        check_returns(&unit.tree)
+    }
+}
+
+// Warn when a function that returns a value can end without a return.
+pub struct MissingReturnCheck;
+
+impl Pass for MissingReturnCheck {
+    fn name(&self) -> &'static str {
+        "missing-return-check"
+    }
+
+    fn run(&self, unit: &mut CompilationUnit) -> Result<(), String> {
+        unit.warnings.extend(missing_return_warnings(&unit.tree));
+        Ok(())
+    }
+}
+
+// Constants have to fit in an int.
+pub struct ConstantCheck;
+
+impl Pass for ConstantCheck {
+    fn name(&self) -> &'static str {
+        "constant-check"
+    }
+
+    fn run(&self, unit: &mut CompilationUnit) -> Result<(), String> {
+        check_constants(&unit.tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parse_program, Parser};
+
+    fn unit(source: &str) -> CompilationUnit {
+        let mut parser = Parser::new(lexer(source.into()));
+        parse_program(&mut parser);
+        assert_eq!(parser.errors, Vec::<String>::new());
+        CompilationUnit {
+            tree: parser.build_tree(),
+            warnings: vec![],
+        }
+    }
+
+    fn check(source: &str) -> Result<(), String> {
+        let mut checks = PassRegistry::default();
+        checks.register_checks();
+        checks.run(&mut unit(source))
+    }
+
+    // Leaves its name behind as a warning, so the order passes ran in shows.
+    struct Noop(&'static str);
+
+    impl Pass for Noop {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn run(&self, unit: &mut CompilationUnit) -> Result<(), String> {
+            unit.warnings.push(Warning {
+                code: self.0,
+                message: String::new(),
+            });
+            Ok(())
+        }
+    }
+
+    struct Fail;
+
+    impl Pass for Fail {
+        fn name(&self) -> &'static str {
+            "fail"
+        }
+
+        fn run(&self, _: &mut CompilationUnit) -> Result<(), String> {
+            Err("failed".into())
+        }
+    }
+
+    #[test]
+    fn passes_run_in_registration_order() {
+        let mut passes = PassRegistry::default();
+        passes.register(Box::new(Noop("first")));
+        passes.register(Box::new(Noop("second")));
+        let mut unit = unit("int main(void) { return 2; }");
+        assert_eq!(passes.run(&mut unit), Ok(()));
+        let ran: Vec<_> = unit.warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(ran, ["first", "second"]);
+    }
+
+    #[test]
+    fn an_error_stops_the_later_passes() {
+        let mut passes = PassRegistry::default();
+        passes.register(Box::new(Noop("first")));
+        passes.register(Box::new(Fail));
+        passes.register(Box::new(Noop("never")));
+        let mut unit = unit("int main(void) { return 2; }");
+        assert_eq!(passes.run(&mut unit), Err("failed".into()));
+        assert_eq!(unit.warnings.len(), 1);
+    }
+
+    #[test]
+    fn prototypes() {
+        assert_eq!(check("int foo(void);"), Ok(()));
+        assert_eq!(
+            check("int foo(void);\nint foo(void) { return 1; }\nint main(void) { return 2; }"),
+            Ok(())
+        );
+        assert_eq!(
+            check("int foo(int);\nint foo(void) { return 1; }"),
+            Err("2:5: conflicting types for foo".into())
+        );
+        assert_eq!(
+            check("void foo(void);\nint foo(void);"),
+            Err("2:5: conflicting types for foo".into())
+        );
+        assert_eq!(
+            check("int foo(int x, int);\nint foo(int a, int b) { return 1; }"),
+            Ok(())
+        );
+        assert_eq!(
+            check("int foo(int x);\nint foo(int x, int y);"),
+            Err("2:5: conflicting types for foo".into())
+        );
+        assert_eq!(
+            check("struct s;\nint foo(struct s x);\nint foo(int x);"),
+            Err("3:5: conflicting types for foo".into())
+        );
+    }
+
+    #[test]
+    fn returns() {
+        assert_eq!(check("void f(void) { return; }"), Ok(()));
+        assert_eq!(
+            check("void f(void) { return 1; }"),
+            Err("1:16: 'return' with a value, in function returning void".into())
+        );
+        assert_eq!(
+            check("int main(void) {\n    return;\n}"),
+            Err("2:5: 'return' with no value, in function returning non-void".into())
+        );
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(check("int main(void) { return 2147483647; }"), Ok(()));
+        assert_eq!(
+            check("int main(void) { return 2147483648; }"),
+            Err("1:25: integer constant out of range: 2147483648".into())
+        );
+        assert_eq!(
+            check("int x = 99999999999999999999;"),
+            Err("1:9: integer constant out of range: 99999999999999999999".into())
+        );
+        assert_eq!(
+            check("int main(void) { return -~(4294967296); }"),
+            Err("1:28: integer constant out of range: 4294967296".into())
+        );
+    }
+}
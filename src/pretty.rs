@@ -0,0 +1,460 @@
+// Reading the output of `Parser::pretty_print` back into a syntax tree.
+//
+// The pretty form names the tokens that vary, like names, types and
+// constants, and leaves out the ones every program has in the same place,
+// like parentheses and semicolons. Those are put back, so the tree has the
+// nodes and tokens the parser would have built from the source, without
+// their spans.
+
+use crate::intern::Interner;
+use crate::{
+    punctuator, Child, Node, NodeId, Span, SyntaxTree, Token, TokenId, TokenKind, TreeKind,
+    MAX_NESTING,
+};
+
+pub fn parse_pretty(text: &str) -> Result<SyntaxTree, String> {
+    let mut lines = Lines {
+        lines: text.lines().map(str::trim).collect(),
+        pos: 0,
+    };
+    let mut tree = Builder::default();
+    lines.expect("Program(")?;
+    tree.open(TreeKind::Program);
+    while !lines.eat(")") {
+        match lines.next()? {
+            "Function(" => function(&mut lines, &mut tree, TreeKind::Function)?,
+            "Prototype(" => function(&mut lines, &mut tree, TreeKind::Prototype)?,
+            "GlobalVar(" => global_var(&mut lines, &mut tree)?,
+            "StructDecl(" => struct_decl(&mut lines, &mut tree)?,
+            line => return Err(lines.error(&format!("unexpected `{line}`"))),
+        }
+    }
+    tree.close();
+    if let Some(line) = lines.peek() {
+        return Err(lines.error(&format!("unexpected `{line}`")));
+    }
+    Ok(tree.finish())
+}
+
+// Puts a tree together the way `Parser::build_tree` does, nodes and tokens
+// numbered in source order.
+#[derive(Default)]
+struct Builder {
+    nodes: Vec<Node>,
+    tokens: Vec<Token>,
+    interner: Interner,
+    // The nodes opened and not yet closed, innermost last.
+    open: Vec<NodeId>,
+}
+
+impl Builder {
+    fn open(&mut self, kind: TreeKind) {
+        let id = NodeId(self.nodes.len());
+        if let Some(&NodeId(parent)) = self.open.last() {
+            self.nodes[parent].children.push(Child::Tree(id));
+        }
+        self.nodes.push(Node {
+            kind,
+            children: vec![],
+        });
+        self.open.push(id);
+    }
+
+    fn close(&mut self) {
+        self.open.pop();
+    }
+
+    fn token(&mut self, kind: TokenKind, text: &str) {
+        let NodeId(parent) = *self.open.last().expect("tokens belong to a node");
+        self.nodes[parent]
+            .children
+            .push(Child::Token(TokenId(self.tokens.len())));
+        self.tokens.push(Token {
+            kind,
+            text: self.interner.intern(text),
+            span: Span::default(),
+        });
+    }
+
+    // A punctuator, whose kind follows from its text.
+    fn punctuator(&mut self, text: &str) {
+        let &(_, kind, _) = punctuator(text).expect("a punctuator");
+        self.token(kind, text);
+    }
+
+    fn finish(self) -> SyntaxTree {
+        assert!(self.open.is_empty());
+        SyntaxTree {
+            nodes: self.nodes,
+            tokens: self.tokens,
+            interner: self.interner,
+        }
+    }
+}
+
+struct Lines<'a> {
+    lines: Vec<&'a str>,
+    pos: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.lines.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let line = self.peek().ok_or("unexpected end of the tree")?;
+        self.pos += 1;
+        Ok(line)
+    }
+
+    fn eat(&mut self, expected: &str) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{expected}`")))
+        }
+    }
+
+    // The value of a `key = value` line, if the next line is one for `key`.
+    fn field(&mut self, key: &str) -> Option<&'a str> {
+        let value = self.peek()?.strip_prefix(key)?.strip_prefix(" = ")?;
+        self.pos += 1;
+        Some(value)
+    }
+
+    // An error about the line just read.
+    fn error_before(&self, message: &str) -> String {
+        format!("line {}: {message}", self.pos)
+    }
+
+    fn error(&self, message: &str) -> String {
+        if self.pos < self.lines.len() {
+            format!("line {}: {message}", self.pos + 1)
+        } else {
+            format!("at the end: {message}")
+        }
+    }
+}
+
+// name = "main"
+// type = "int"
+// params = "void"
+// body = Return(
+//     Constant(2)
+// )
+//
+// A Prototype has no body.
+fn function(lines: &mut Lines, tree: &mut Builder, kind: TreeKind) -> Result<(), String> {
+    tree.open(kind);
+    let name = name(lines)?;
+    ty(lines, tree)?;
+    tree.token(TokenKind::Identifier, name);
+    tree.punctuator("(");
+    params(lines, tree)?;
+    tree.punctuator(")");
+    if kind == TreeKind::Prototype {
+        tree.punctuator(";");
+    } else {
+        tree.punctuator("{");
+        while let Some(kind) = lines.field("body") {
+            match kind {
+                "Return(" => {
+                    tree.open(TreeKind::Return);
+                    tree.token(TokenKind::Keyword, "return");
+                    let line = lines.next()?;
+                    if line != ")" {
+                        exp(lines, tree, line, 0)?;
+                        lines.expect(")")?;
+                    }
+                    tree.punctuator(";");
+                }
+                "InlineAsm(" => {
+                    tree.open(TreeKind::InlineAsm);
+                    tree.token(TokenKind::Keyword, "__asm__");
+                    tree.punctuator("(");
+                    let line = lines.next()?;
+                    let text = wrapped(lines, line, "StringLiteral")?;
+                    tree.token(TokenKind::StringLiteral, text);
+                    tree.punctuator(")");
+                    tree.punctuator(";");
+                    lines.expect(")")?;
+                }
+                _ => return Err(lines.error(&format!("can't rebuild a body of `{kind}`"))),
+            }
+            tree.close();
+        }
+        tree.punctuator("}");
+    }
+    tree.close();
+    lines.expect(")")
+}
+
+// params = "void", or
+//
+// param = Param(
+//     name = "x"
+//     type = "int"
+// )
+//
+// for each parameter, where the name is optional.
+fn params(lines: &mut Lines, tree: &mut Builder) -> Result<(), String> {
+    tree.open(TreeKind::Params);
+    if lines
+        .peek()
+        .is_some_and(|line| line.starts_with("params = "))
+    {
+        let params = quoted(lines, "params")?;
+        if params != "void" {
+            return Err(lines.error_before(&format!("unknown parameter list `{params}`")));
+        }
+        tree.token(TokenKind::Keyword, params);
+    } else {
+        let mut count = 0;
+        while let Some(kind) = lines.field("param") {
+            if kind != "Param(" {
+                return Err(lines.error(&format!("can't rebuild a parameter of `{kind}`")));
+            }
+            if count > 0 {
+                tree.punctuator(",");
+            }
+            count += 1;
+            tree.open(TreeKind::Param);
+            let name = lines
+                .peek()
+                .is_some_and(|line| line.starts_with("name = "))
+                .then(|| self::name(lines))
+                .transpose()?;
+            ty(lines, tree)?;
+            if let Some(name) = name {
+                tree.token(TokenKind::Identifier, name);
+            }
+            tree.close();
+            lines.expect(")")?;
+        }
+        if count == 0 {
+            return Err(lines.error("expected `params = \"void\"` or a `param`"));
+        }
+    }
+    tree.close();
+    Ok(())
+}
+
+// Constant(2), or
+//
+// Unary(
+//     op = "!"
+//     Paren(
+//         Constant(2)
+//     )
+// )
+fn exp<'a>(
+    lines: &mut Lines<'a>,
+    tree: &mut Builder,
+    line: &'a str,
+    depth: usize,
+) -> Result<(), String> {
+    let kind = match line {
+        "Unary(" => TreeKind::Unary,
+        "Paren(" => TreeKind::Paren,
+        _ => {
+            let text = wrapped(lines, line, "Constant")?;
+            tree.token(TokenKind::Constant, text);
+            return Ok(());
+        }
+    };
+    if depth == MAX_NESTING {
+        return Err(lines.error("expression nested too deeply"));
+    }
+    tree.open(kind);
+    if kind == TreeKind::Unary {
+        let op = quoted(lines, "op")?;
+        if !matches!(op, "-" | "~" | "!") {
+            return Err(lines.error_before(&format!("unknown operator `{op}`")));
+        }
+        tree.punctuator(op);
+    } else {
+        tree.punctuator("(");
+    }
+    let line = lines.next()?;
+    exp(lines, tree, line, depth + 1)?;
+    lines.expect(")")?;
+    if kind == TreeKind::Paren {
+        tree.punctuator(")");
+    }
+    tree.close();
+    Ok(())
+}
+
+// name = "x"
+// type = "struct point"
+// init = Constant(3)
+fn global_var(lines: &mut Lines, tree: &mut Builder) -> Result<(), String> {
+    tree.open(TreeKind::GlobalVar);
+    let name = name(lines)?;
+    ty(lines, tree)?;
+    tree.token(TokenKind::Identifier, name);
+    if let Some(init) = lines.field("init") {
+        let init = wrapped(lines, init, "Constant")?;
+        tree.punctuator("=");
+        tree.token(TokenKind::Constant, init);
+    }
+    tree.punctuator(";");
+    tree.close();
+    lines.expect(")")
+}
+
+// name = "point"
+// member = MemberDecl(
+//     name = "x"
+//     type = "int"
+// )
+//
+// A struct without members is a forward declaration.
+fn struct_decl(lines: &mut Lines, tree: &mut Builder) -> Result<(), String> {
+    tree.open(TreeKind::StructDecl);
+    let name = self::name(lines)?;
+    tree.token(TokenKind::Keyword, "struct");
+    tree.token(TokenKind::Identifier, name);
+    let mut braced = false;
+    while let Some(kind) = lines.field("member") {
+        if kind != "MemberDecl(" {
+            return Err(lines.error(&format!("can't rebuild a member of `{kind}`")));
+        }
+        if !braced {
+            tree.punctuator("{");
+            braced = true;
+        }
+        tree.open(TreeKind::MemberDecl);
+        let name = self::name(lines)?;
+        ty(lines, tree)?;
+        tree.token(TokenKind::Identifier, name);
+        tree.punctuator(";");
+        tree.close();
+        lines.expect(")")?;
+    }
+    if braced {
+        tree.punctuator("}");
+    }
+    tree.punctuator(";");
+    tree.close();
+    lines.expect(")")
+}
+
+// The type of a declaration. Without a `type` line, it's an implicit int.
+fn ty(lines: &mut Lines, tree: &mut Builder) -> Result<(), String> {
+    if lines
+        .peek()
+        .is_some_and(|line| !line.starts_with("type = "))
+    {
+        tree.open(TreeKind::ImplicitInt);
+        tree.close();
+        return Ok(());
+    }
+    let ty = quoted(lines, "type")?;
+    if let Some(tag) = ty.strip_prefix("struct ") {
+        tree.open(TreeKind::StructType);
+        tree.token(TokenKind::Keyword, "struct");
+        tree.token(TokenKind::Identifier, tag);
+        tree.close();
+    } else if matches!(ty, "int" | "void") {
+        tree.token(TokenKind::Keyword, ty);
+    } else {
+        return Err(lines.error_before(&format!("unknown type `{ty}`")));
+    }
+    Ok(())
+}
+
+fn name<'a>(lines: &mut Lines<'a>) -> Result<&'a str, String> {
+    quoted(lines, "name")
+}
+
+// The value of a `key = "value"` line.
+fn quoted<'a>(lines: &mut Lines<'a>, key: &str) -> Result<&'a str, String> {
+    lines
+        .field(key)
+        .and_then(|value| value.strip_prefix('"')?.strip_suffix('"'))
+        .ok_or_else(|| lines.error(&format!("expected `{key} = \"...\"`")))
+}
+
+// The text of `Kind(text)`.
+fn wrapped<'a>(lines: &mut Lines<'a>, line: &'a str, kind: &str) -> Result<&'a str, String> {
+    line.strip_prefix(kind)
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.strip_suffix(')'))
+        .ok_or_else(|| lines.error(&format!("expected `{kind}(...)`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parse_program, tree_to_dot, Parser, Standard};
+
+    fn pretty(tree: &SyntaxTree) -> String {
+        let mut output = String::new();
+        Parser::pretty_print(tree, tree.root(), 0, true, &mut output);
+        output
+    }
+
+    fn round_trip(source: &str) {
+        let mut parser = Parser::new(lexer(source.into()));
+        parser.standard = Standard::C89;
+        parse_program(&mut parser);
+        assert_eq!(parser.errors, Vec::<String>::new());
+        let tree = parser.build_tree();
+        let printed = pretty(&tree);
+        let reread = parse_pretty(&printed).unwrap();
+        // The DOT graph has every node kind, token kind and token text in
+        // tree order, and no spans.
+        assert_eq!(tree_to_dot(&reread), tree_to_dot(&tree), "{printed}");
+    }
+
+    #[test]
+    fn canonical_program_round_trips() {
+        round_trip("int main(void) {\n    return 2;\n}\n");
+    }
+
+    #[test]
+    fn void_function_round_trips() {
+        round_trip("void f(void) { return; }");
+    }
+
+    #[test]
+    fn every_item_kind_round_trips() {
+        round_trip(
+            "struct point { int x; int y; };\nstruct later;\nstruct point origin;\nint g = 3;\n\
+             h;\nint f(int);\nint named(int x, struct point p, int);\nvoid v(void);\nint main(void) { __asm__(\"nop\"); return -~!(2); }\n\
+             implicit(void) { return ((1)); }",
+        );
+    }
+
+    #[test]
+    fn malformed_trees_are_errors() {
+        assert_eq!(
+            parse_pretty("Function(\n)\n"),
+            Err("line 1: expected `Program(`".into())
+        );
+        assert_eq!(
+            parse_pretty("Program(\n    Function(\n    )\n)\n"),
+            Err("line 3: expected `name = \"...\"`".into())
+        );
+        assert_eq!(
+            parse_pretty(
+                "Program(\n    GlobalVar(\n        name = \"x\"\n        type = \"long\"\n"
+            ),
+            Err("line 4: unknown type `long`".into())
+        );
+        assert_eq!(
+            parse_pretty("Program(\n"),
+            Err("unexpected end of the tree".into())
+        );
+    }
+}
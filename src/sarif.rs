@@ -0,0 +1,236 @@
+// Diagnostics as a SARIF 2.1.0 log, the JSON format code scanning tools
+// read. There is one run, of zcc, whose rules are the warning codes, and a
+// result for each diagnostic.
+//
+// Diagnostics are the messages the driver prints, so the location is read
+// back from their `file:line:col: ` prefix, and the code of a warning
+// promoted to an error from its ` [W0002]` suffix. A message without a file
+// has no location.
+
+use crate::diagnostics::{Warning, CODES};
+use crate::{json_escape, CompileError};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    /// The warning code, for warnings and the errors promoted from them
+    pub code: Option<&'static str>,
+    /// As the driver prints it, with its location
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(warning: &Warning) -> Diagnostic {
+        Diagnostic {
+            level: Level::Warning,
+            code: Some(warning.code),
+            message: warning.message.clone(),
+        }
+    }
+
+    /// One diagnostic for each message of `error`.
+    pub fn errors(error: &CompileError) -> Vec<Diagnostic> {
+        error
+            .messages()
+            .into_iter()
+            .map(|message| {
+                let code = CODES
+                    .iter()
+                    .map(|&(code, _)| code)
+                    .find(|code| message.ends_with(&format!(" [{code}]")));
+                let message = match code {
+                    Some(code) => message[..message.len() - code.len() - 3].to_string(),
+                    None => message,
+                };
+                Diagnostic {
+                    level: Level::Error,
+                    code,
+                    message,
+                }
+            })
+            .collect()
+    }
+}
+
+// Where a message points, split off the text after it.
+#[derive(Debug, PartialEq)]
+struct Location<'a> {
+    file: &'a str,
+    line: u32,
+    col: u32,
+}
+
+fn split_location(message: &str) -> (Option<Location<'_>>, &str) {
+    let Some((head, text)) = message.split_once(": ") else {
+        return (None, message);
+    };
+    let mut parts = head.rsplitn(3, ':');
+    let (Some(col), Some(line), Some(file)) = (parts.next(), parts.next(), parts.next()) else {
+        return (None, message);
+    };
+    match (line.parse(), col.parse()) {
+        (Ok(line), Ok(col)) if !file.is_empty() => (Some(Location { file, line, col }), text),
+        _ => (None, message),
+    }
+}
+
+// A relative path is a relative URI reference, as long as the characters
+// URIs reserve are escaped.
+fn uri(path: &str) -> String {
+    let mut uri = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '\\' => uri.push('/'),
+            '%' | ' ' | '#' | '?' => uri.push_str(&format!("%{:02X}", c as u32)),
+            c => uri.push(c),
+        }
+    }
+    uri
+}
+
+/// The SARIF log of `diagnostics`, as pretty-printed JSON.
+pub fn log(diagnostics: &[Diagnostic]) -> String {
+    let rules: Vec<String> = CODES
+        .iter()
+        .map(|(code, description)| {
+            format!(
+                "{{\"id\": \"{code}\", \"shortDescription\": {{\"text\": \"{}\"}}}}",
+                json_escape(description)
+            )
+        })
+        .collect();
+    let results: Vec<String> = diagnostics.iter().map(result).collect();
+    format!(
+        "{{\n  \"$schema\": \"https://json.schemastore.org/sarif-2.1.0.json\",\n  \
+         \"version\": \"2.1.0\",\n  \"runs\": [\n    {{\n      \"tool\": {{\n        \
+         \"driver\": {{\n          \"name\": \"zcc\",\n          \"version\": \"{}\",\n          \
+         \"rules\": [\n            {}\n          ]\n        }}\n      }},\n      \
+         \"results\": [{}]\n    }}\n  ]\n}}\n",
+        env!("CARGO_PKG_VERSION"),
+        rules.join(",\n            "),
+        if results.is_empty() {
+            String::new()
+        } else {
+            format!("\n        {}\n      ", results.join(",\n        "))
+        }
+    )
+}
+
+// One result, on one line. The region is the character the message points
+// at; the diagnostics don't say how far the problem goes.
+fn result(diagnostic: &Diagnostic) -> String {
+    let (location, text) = split_location(&diagnostic.message);
+    let level = match diagnostic.level {
+        Level::Error => "error",
+        Level::Warning => "warning",
+    };
+    let mut result = String::from("{");
+    if let Some(code) = diagnostic.code {
+        let index = CODES.iter().position(|&(known, _)| known == code);
+        result.push_str(&format!("\"ruleId\": \"{code}\", "));
+        if let Some(index) = index {
+            result.push_str(&format!("\"ruleIndex\": {index}, "));
+        }
+    }
+    result.push_str(&format!(
+        "\"level\": \"{level}\", \"message\": {{\"text\": \"{}\"}}",
+        json_escape(text)
+    ));
+    if let Some(Location { file, line, col }) = location {
+        result.push_str(&format!(
+            ", \"locations\": [{{\"physicalLocation\": {{\"artifactLocation\": {{\"uri\": \"{}\"}}, \
+             \"region\": {{\"startLine\": {line}, \"startColumn\": {col}, \"endLine\": {line}, \
+             \"endColumn\": {}}}}}}}]",
+            json_escape(&uri(file)),
+            col + 1
+        ));
+    }
+    result.push('}');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::MISSING_RETURN;
+
+    #[test]
+    fn locations_are_read_from_the_message() {
+        assert_eq!(
+            split_location("f.c:12:3: expected Semicolon"),
+            (
+                Some(Location {
+                    file: "f.c",
+                    line: 12,
+                    col: 3
+                }),
+                "expected Semicolon"
+            )
+        );
+        assert_eq!(
+            split_location("C:\\src\\f.c:1:2: x").0.map(|l| l.file),
+            Some("C:\\src\\f.c")
+        );
+        assert_eq!(split_location("12:3: no file").0, None);
+        assert_eq!(
+            split_location("end of input: expected CloseBrace"),
+            (None, "end of input: expected CloseBrace")
+        );
+        assert_eq!(split_location("no colon at all"), (None, "no colon at all"));
+    }
+
+    #[test]
+    fn promoted_warnings_keep_their_code() {
+        let error = CompileError::Semantic(vec![
+            "f.c:3:1: non-void function can end without a return [W0002]".into(),
+            "f.c:4:1: something else".into(),
+        ]);
+        let diagnostics = Diagnostic::errors(&error);
+        assert_eq!(diagnostics[0].code, Some(MISSING_RETURN));
+        assert_eq!(
+            diagnostics[0].message,
+            "f.c:3:1: non-void function can end without a return"
+        );
+        assert_eq!(diagnostics[1].code, None);
+        assert_eq!(diagnostics[1].level, Level::Error);
+    }
+
+    #[test]
+    fn a_log_has_one_run_with_rules_and_results() {
+        let warning = Warning {
+            code: MISSING_RETURN,
+            message: "dir/my f.c:3:1: control reaches the end".into(),
+        };
+        let log = log(&[
+            Diagnostic::warning(&warning),
+            Diagnostic::errors(&CompileError::Parse(vec!["end of input: \"}\"".into()])).remove(0),
+        ]);
+        assert!(log.contains("\"version\": \"2.1.0\""), "{log}");
+        assert_eq!(log.matches("\"tool\"").count(), 1);
+        assert!(log.contains("\"name\": \"zcc\""), "{log}");
+        assert_eq!(log.matches("\"id\": \"W000").count(), CODES.len());
+        assert!(
+            log.contains(
+                "{\"ruleId\": \"W0002\", \"ruleIndex\": 1, \"level\": \"warning\", \
+                 \"message\": {\"text\": \"control reaches the end\"}, \"locations\": \
+                 [{\"physicalLocation\": {\"artifactLocation\": {\"uri\": \"dir/my%20f.c\"}, \
+                 \"region\": {\"startLine\": 3, \"startColumn\": 1, \"endLine\": 3, \
+                 \"endColumn\": 2}}}]}"
+            ),
+            "{log}"
+        );
+        assert!(
+            log.contains(
+                "{\"level\": \"error\", \"message\": {\"text\": \"end of input: \\\"}\\\"\"}}"
+            ),
+            "{log}"
+        );
+        assert!(self::log(&[]).contains("\"results\": []"));
+    }
+}
@@ -0,0 +1,308 @@
+// TACKY, the three-address code between the syntax tree and assembly. Every
+// subexpression gets its result in a temporary of its own, so assembly
+// generation only ever sees one operator at a time.
+//
+// program = Program(function_definition*, static_variable*)
+// function_definition = Function(identifier, instruction* body)
+// static_variable = StaticVariable(identifier name, int? init)
+// instruction = Return(val?)
+//             | Unary(unary_operator, val src, val dst)
+//             | Binary(binary_operator, val src1, val src2, val dst)
+//             | InlineAsm(string)
+//             | Loc(span)
+// val = Constant(int) | Var(identifier)
+// unary_operator = Complement | Negate | Not
+// binary_operator = Add | Subtract | Multiply
+
+use crate::{
+    constant_value, strip_parens, Child, NodeId, Span, Symbol, SyntaxTree, Token, TokenKind,
+    TreeKind,
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TackyProgram {
+    pub functions: Vec<TackyFunction>,
+    pub statics: Vec<TackyStaticVariable>,
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct TackyStaticVariable {
+    pub identifier: Symbol,
+    pub init: Option<i64>,
+}
+#[derive(Debug, PartialEq, Clone)]
+pub struct TackyFunction {
+    pub identifier: Symbol,
+    pub instructions: Vec<TackyInstruction>,
+}
+#[derive(Debug, PartialEq, Clone)]
+pub enum TackyInstruction {
+    Return(Option<TackyVal>),
+    Unary {
+        op: TackyUnaryOperator,
+        src: TackyVal,
+        dst: TackyVal,
+    },
+    Binary {
+        op: TackyBinaryOperator,
+        src1: TackyVal,
+        src2: TackyVal,
+        dst: TackyVal,
+    },
+    InlineAsm(String),
+    // Where the statement the instructions after it come from starts, for
+    // the debug info.
+    Loc(Span),
+}
+#[derive(Debug, PartialEq, Clone)]
+pub enum TackyVal {
+    Constant(i64),
+    // A temporary, named so it can't clash with anything in the source.
+    Var(Symbol),
+}
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TackyUnaryOperator {
+    Complement,
+    Negate,
+    Not,
+}
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TackyBinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+}
+
+// The tree should have passed the checks in `passes` first. Errors are for
+// what the language allows but zcc can't compile yet, like struct variables.
+// Anything else wrong with the tree is a bug and panics. The names of the
+// temporaries are interned in the tree's interner.
+pub fn generate_tacky(tree: &mut SyntaxTree) -> Result<TackyProgram, String> {
+    // Every temporary holds the result of a unary operator, so the function
+    // with the most of them says how many names there have to be. Functions
+    // share them, and they're interned up front so generating only reads the
+    // tree.
+    let most = tree
+        .children(tree.root())
+        .iter()
+        .filter_map(|child| match child {
+            Child::Tree(t) if tree.kind(*t) == TreeKind::Function => Some(count_unary(tree, *t)),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let temporaries: Vec<Symbol> = (0..most)
+        .map(|i| tree.interner.intern(&format!("tmp.{i}")))
+        .collect();
+    let tree = &*tree;
+    let program = tree.root();
+    match tree.kind(program) {
+        TreeKind::Program => {
+            let mut functions = vec![];
+            let mut statics = vec![];
+            for child in tree.children(program) {
+                if let Child::Tree(t) = child {
+                    match tree.kind(*t) {
+                        TreeKind::Function => {
+                            functions.push(generate_function(tree, *t, &temporaries)?)
+                        }
+                        TreeKind::GlobalVar => statics.push(generate_static(tree, *t)?),
+                        _ => {}
+                    }
+                }
+            }
+            Ok(TackyProgram { functions, statics })
+        }
+        _ => panic!("should have been a program here."),
+    }
+}
+
+fn count_unary(tree: &SyntaxTree, id: NodeId) -> usize {
+    let own = usize::from(tree.kind(id) == TreeKind::Unary);
+    own + tree
+        .children(id)
+        .iter()
+        .map(|child| match child {
+            Child::Tree(t) => count_unary(tree, *t),
+            Child::Token(_) => 0,
+        })
+        .sum::<usize>()
+}
+
+// The instructions of one function, with its temporaries numbered from 0.
+struct FunctionBuilder<'a> {
+    tree: &'a SyntaxTree,
+    instructions: Vec<TackyInstruction>,
+    // The names `tmp.0`, `tmp.1` and so on, enough for this function.
+    names: &'a [Symbol],
+    temporaries: usize,
+}
+
+impl FunctionBuilder<'_> {
+    fn make_temporary(&mut self) -> TackyVal {
+        let name = self.names[self.temporaries];
+        self.temporaries += 1;
+        TackyVal::Var(name)
+    }
+}
+
+fn generate_function(
+    tree: &SyntaxTree,
+    function: NodeId,
+    names: &[Symbol],
+) -> Result<TackyFunction, String> {
+    match tree.kind(function) {
+        TreeKind::Function => {
+            let returns_void = tree
+                .token_child(function, 0)
+                .is_some_and(|token| token.text == Symbol::VOID);
+            if let Some(Token {
+                text,
+                kind: TokenKind::Identifier,
+                ..
+            }) = tree.token_child(function, 1)
+            {
+                let mut builder = FunctionBuilder {
+                    tree,
+                    instructions: vec![],
+                    names,
+                    temporaries: 0,
+                };
+                for statement in tree.statements(function) {
+                    if let Some(token) = tree.token_child(statement, 0) {
+                        builder.instructions.push(TackyInstruction::Loc(token.span));
+                    }
+                    match tree.kind(statement) {
+                        TreeKind::Return => generate_return(&mut builder, statement)?,
+                        TreeKind::InlineAsm => builder
+                            .instructions
+                            .push(generate_inline_asm(tree, statement)),
+                        kind => panic!("unexpected statement {kind:?}"),
+                    }
+                }
+                // A body that doesn't end in a return returns 0, so there is
+                // always a return.
+                if !matches!(
+                    builder.instructions.last(),
+                    Some(TackyInstruction::Return(_))
+                ) {
+                    let value = (!returns_void).then_some(TackyVal::Constant(0));
+                    builder.instructions.push(TackyInstruction::Return(value));
+                }
+                Ok(TackyFunction {
+                    identifier: *text,
+                    instructions: builder.instructions,
+                })
+            } else {
+                panic!("could not find identifier");
+            }
+        }
+        _ => panic!("should have been a function."),
+    }
+}
+
+fn generate_static(tree: &SyntaxTree, global: NodeId) -> Result<TackyStaticVariable, String> {
+    // Structs are parsed, but there is no layout for them yet.
+    if let Some(Child::Tree(ty)) = tree.children(global).first() {
+        if tree.kind(*ty) == TreeKind::StructType {
+            let at = tree.token_child(*ty, 0).map(|token| token.span);
+            return Err(format!(
+                "{}: variables of struct type aren't supported yet",
+                tree.location(at.unwrap_or_default())
+            ));
+        }
+    }
+    if let Some(Token {
+        text,
+        kind: TokenKind::Identifier,
+        ..
+    }) = tree.token_child(global, 1)
+    {
+        Ok(TackyStaticVariable {
+            identifier: *text,
+            init: tree
+                .token_child(global, 3)
+                .map(|init| constant_value(tree, init, false))
+                .transpose()?,
+        })
+    } else {
+        panic!("could not find identifier");
+    }
+}
+
+fn generate_return(builder: &mut FunctionBuilder, statement: NodeId) -> Result<(), String> {
+    let tree = builder.tree;
+    match tree.kind(statement) {
+        TreeKind::Return => match tree.children(statement).get(1) {
+            Some(Child::Token(token)) if tree.token(*token).kind == TokenKind::Semicolon => {
+                builder.instructions.push(TackyInstruction::Return(None));
+                Ok(())
+            }
+            Some(exp) => {
+                let value = generate_exp(builder, exp)?;
+                builder
+                    .instructions
+                    .push(TackyInstruction::Return(Some(value)));
+                Ok(())
+            }
+            None => panic!("No constant found where one was expected"),
+        },
+        _ => panic!("should have been a function."),
+    }
+}
+
+// Emit the instructions computing `exp` and give the value holding its result.
+fn generate_exp(builder: &mut FunctionBuilder, exp: &Child) -> Result<TackyVal, String> {
+    let tree = builder.tree;
+    match strip_parens(tree, exp) {
+        Child::Token(token) => Ok(TackyVal::Constant(constant_value(
+            tree,
+            tree.token(*token),
+            false,
+        )?)),
+        Child::Tree(unary) => {
+            let operand = tree
+                .children(*unary)
+                .get(1)
+                .expect("unary operator without an operand");
+            let op = match tree.token_child(*unary, 0).map(|op| op.kind) {
+                // A negated literal is a constant of its own, which is how
+                // -2147483648 is an int.
+                Some(TokenKind::Minus) => match strip_parens(tree, operand) {
+                    Child::Token(token) => {
+                        let value = constant_value(tree, tree.token(*token), true)?;
+                        return Ok(TackyVal::Constant(value));
+                    }
+                    Child::Tree(_) => TackyUnaryOperator::Negate,
+                },
+                Some(TokenKind::Tilde) => TackyUnaryOperator::Complement,
+                Some(TokenKind::Bang) => TackyUnaryOperator::Not,
+                op => panic!("unexpected unary operator {op:?}"),
+            };
+            let src = generate_exp(builder, operand)?;
+            let dst = builder.make_temporary();
+            builder.instructions.push(TackyInstruction::Unary {
+                op,
+                src,
+                dst: dst.clone(),
+            });
+            Ok(dst)
+        }
+    }
+}
+
+// The string goes into the output as it is, without its quotes. Escapes
+// aren't interpreted.
+fn generate_inline_asm(tree: &SyntaxTree, statement: NodeId) -> TackyInstruction {
+    match tree.token_child(statement, 2) {
+        Some(
+            token @ Token {
+                kind: TokenKind::StringLiteral,
+                ..
+            },
+        ) => {
+            let text = tree.text(token);
+            TackyInstruction::InlineAsm(text[1..text.len() - 1].into())
+        }
+        _ => panic!("No string found where one was expected"),
+    }
+}
@@ -0,0 +1,108 @@
+// Entry points for the web playground. They only take and return strings, so
+// they build for wasm32-unknown-unknown where there is no filesystem or gcc.
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    compile_source, json_escape, lexer, parse_program, CompileOptions, Parser, SyntaxTree,
+    TokenStream,
+};
+
+// [{"kind":"Keyword","text":"int"},{"kind":"Identifier","text":"main"},...]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn lex_json(source: &str) -> String {
+    let tokens = lexer(source.into());
+    let mut output = String::from("[");
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        output.push_str(&format!(
+            "{{\"kind\":\"{:?}\",\"text\":\"{}\"}}",
+            token.kind,
+            json_escape(tokens.text(token))
+        ));
+    }
+    output.push(']');
+    output
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn parse_tree_text(source: &str) -> String {
+    let (tree, errors) = parse(lexer(source.into()));
+    let mut output = String::new();
+    Parser::pretty_print(&tree, tree.root(), 0, true, &mut output);
+    for error in errors {
+        output.push_str(&error);
+        output.push('\n');
+    }
+    output
+}
+
+// The warnings come first, as assembly comments, so the playground shows them
+// without a second call.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn compile_asm(source: &str) -> Result<String, String> {
+    let output = compile_source(source, &CompileOptions::default(), None)
+        .map_err(|error| error.to_string())?;
+    let mut assembly = String::new();
+    for warning in &output.warnings {
+        assembly.push_str(&format!(
+            "# warning: {} [{}]\n",
+            warning.message, warning.code
+        ));
+    }
+    assembly.push_str(&output.assembly);
+    Ok(assembly)
+}
+
+fn parse(tokens: TokenStream) -> (SyntaxTree, Vec<String>) {
+    let mut parser = Parser::new(tokens);
+    parse_program(&mut parser);
+    let errors = std::mem::take(&mut parser.errors);
+    (parser.build_tree(), errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lex_json_lists_kinds_and_escaped_text() {
+        assert_eq!(
+            lex_json("return \"a\\b\";"),
+            "[{\"kind\":\"Keyword\",\"text\":\"return\"},\
+             {\"kind\":\"StringLiteral\",\"text\":\"\\\"a\\\\b\\\"\"},\
+             {\"kind\":\"Semicolon\",\"text\":\";\"}]"
+        );
+    }
+
+    #[test]
+    fn parse_tree_text_appends_the_errors() {
+        let text = parse_tree_text("int main(void) { return 2 }");
+        assert!(text.starts_with("Program(\n"), "{text}");
+        assert!(text.ends_with(")\n1:27: expected Semicolon\n"), "{text}");
+    }
+
+    #[test]
+    fn compile_asm_returns_assembly_or_the_message() {
+        assert!(compile_asm("int main(void) { return 2; }")
+            .unwrap()
+            .contains("main:"));
+        assert_eq!(
+            compile_asm("int main(void) { return 2147483648; }"),
+            Err("1:25: integer constant out of range: 2147483648".into())
+        );
+    }
+    #[test]
+    fn compile_asm_puts_the_warnings_first() {
+        let asm = compile_asm("int main(void) { return 1; return 2; }").unwrap();
+        assert!(
+            asm.starts_with(
+                "# warning: 1:28: code after return in main will never be executed [W0004]\n"
+            ),
+            "{asm}"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+// Counts the allocations of parsing a fixed program, so a change that makes
+// build_tree allocate per node again shows up as a failure.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use zcc::{lexer, parse_program, Parser};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[test]
+fn parsing_allocates_per_tree_not_per_token() {
+    let source: String = (0..200)
+        .map(|i| format!("int f{i}(void) {{\n    return -~{i};\n}}\n"))
+        .collect();
+    let tokens = lexer(source);
+    let token_count = tokens.len();
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let mut parser = Parser::new(tokens);
+    parse_program(&mut parser);
+    let tree = parser.build_tree();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    drop(tree);
+
+    // Function, Params, Return and two Unary trees per function, each with
+    // its children in one vector, plus some growing of the parser's buffers.
+    let trees = 5 * 200 + 1;
+    assert!(
+        allocations <= trees + 100,
+        "{allocations} allocations for {trees} trees and {token_count} tokens"
+    );
+}
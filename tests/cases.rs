@@ -0,0 +1,340 @@
+// Runs every C file under tests/cases as its own test. The `//` lines at the
+// top of a file say what should happen to it, one `key: value` per line:
+//
+//     // compile: ok | lex-error | parse-error | semantic-error
+//     // exit: 42          status the program exits with
+//     // stdout: hello     one line of what it prints, repeat for more lines
+//     // flags: --std c89  extra driver flags
+//     // target: linux     only run on this OS, like a raw syscall in asm
+//
+// `compile` is required. The header ends at the first line that isn't a `//`
+// comment, and every line in it has to be an expectation, so a typo fails the
+// test instead of being skipped. Files that only expect a compile result and
+// pass no flags go through the library; the rest are built by the driver,
+// and run when they expect an exit status or output.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use common::installed;
+use zcc::{compile_bytes, CompileError, CompileOptions};
+
+mod common;
+
+const CASES: &str = "tests/cases";
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Compile {
+    Ok,
+    LexError,
+    ParseError,
+    SemanticError,
+}
+
+impl Compile {
+    fn parse(text: &str) -> Option<Compile> {
+        match text {
+            "ok" => Some(Compile::Ok),
+            "lex-error" => Some(Compile::LexError),
+            "parse-error" => Some(Compile::ParseError),
+            "semantic-error" => Some(Compile::SemanticError),
+            _ => None,
+        }
+    }
+
+    // The driver's exit status for each outcome.
+    fn from_status(code: i32) -> Option<Compile> {
+        match code {
+            0 => Some(Compile::Ok),
+            1 => Some(Compile::LexError),
+            2 => Some(Compile::ParseError),
+            9 => Some(Compile::SemanticError),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Expectations {
+    compile: Option<Compile>,
+    exit: Option<i32>,
+    stdout: Option<String>,
+    flags: Vec<String>,
+    target: Option<String>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Result<Expectations, String> {
+        let mut expected = Expectations::default();
+        for (number, line) in source.lines().enumerate() {
+            let Some(comment) = line.strip_prefix("//") else {
+                break;
+            };
+            let malformed = |what: &str| format!("line {}: {what}: {line}", number + 1);
+            let Some((key, value)) = comment.split_once(':') else {
+                return Err(malformed("expected `// key: value`"));
+            };
+            let value = value.trim();
+            match key.trim() {
+                "compile" if expected.compile.is_some() => return Err(malformed("repeated")),
+                "compile" => {
+                    let compile = Compile::parse(value).ok_or_else(|| {
+                        malformed("expected ok, lex-error, parse-error or semantic-error")
+                    })?;
+                    expected.compile = Some(compile);
+                }
+                "exit" if expected.exit.is_some() => return Err(malformed("repeated")),
+                "exit" => {
+                    let code = value
+                        .parse()
+                        .map_err(|_| malformed("expected an exit status"))?;
+                    expected.exit = Some(code);
+                }
+                "stdout" => {
+                    let stdout = expected.stdout.get_or_insert_with(String::new);
+                    stdout.push_str(value);
+                    stdout.push('\n');
+                }
+                "flags" if !expected.flags.is_empty() => return Err(malformed("repeated")),
+                "flags" if value.is_empty() => return Err(malformed("no flags")),
+                "flags" => expected.flags = value.split_whitespace().map(String::from).collect(),
+                "target" if expected.target.is_some() => return Err(malformed("repeated")),
+                "target" => expected.target = Some(value.to_string()),
+                _ => return Err(malformed("unknown expectation")),
+            }
+        }
+        match expected.compile {
+            None => Err("missing `// compile:`".into()),
+            Some(compile) if compile != Compile::Ok && expected.runs() => {
+                Err("`exit` and `stdout` need `compile: ok`".into())
+            }
+            Some(_) => Ok(expected),
+        }
+    }
+
+    fn runs(&self) -> bool {
+        self.exit.is_some() || self.stdout.is_some()
+    }
+
+    fn needs_driver(&self) -> bool {
+        self.runs() || !self.flags.is_empty()
+    }
+}
+
+enum Outcome {
+    Passed,
+    Failed(String),
+    Ignored(&'static str),
+}
+
+fn run_case(name: &str, path: &Path) -> Outcome {
+    let source = match fs::read(path) {
+        Ok(source) => source,
+        Err(error) => return Outcome::Failed(error.to_string()),
+    };
+    let expected = match Expectations::parse(&String::from_utf8_lossy(&source)) {
+        Ok(expected) => expected,
+        Err(error) => return Outcome::Failed(format!("malformed expectations, {error}")),
+    };
+    if expected
+        .target
+        .as_ref()
+        .is_some_and(|target| target != env::consts::OS)
+    {
+        return Outcome::Ignored("it's for another target");
+    }
+    let result = if expected.needs_driver() {
+        if !installed("gcc") {
+            return Outcome::Ignored("gcc isn't installed");
+        }
+        run_driver(name, path, &expected)
+    } else {
+        run_library(&source, &expected)
+    };
+    match result {
+        Ok(()) => Outcome::Passed,
+        Err(error) => Outcome::Failed(error),
+    }
+}
+
+fn run_library(source: &[u8], expected: &Expectations) -> Result<(), String> {
+    let (compile, messages) = match compile_bytes(source, CompileOptions::default()) {
+        Ok(_) => (Compile::Ok, vec![]),
+        Err(error @ CompileError::Lex(_)) => (Compile::LexError, error.messages()),
+        Err(error @ CompileError::Parse(_)) => (Compile::ParseError, error.messages()),
+        Err(error @ CompileError::Semantic(_)) => (Compile::SemanticError, error.messages()),
+        Err(error) => return Err(format!("compile failed: {error}")),
+    };
+    check_compile(expected, compile, &messages.join("\n"))
+}
+
+fn run_driver(name: &str, path: &Path, expected: &Expectations) -> Result<(), String> {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("cases")
+        .join(name.replace(['/', '.'], "_"));
+    fs::create_dir_all(&dir).map_err(|error| error.to_string())?;
+    // The driver writes its .i and .s files next to the source, so it gets a
+    // copy to keep them out of the source tree.
+    let source = dir.join(path.file_name().unwrap());
+    fs::copy(path, &source).map_err(|error| error.to_string())?;
+    let program = dir.join("program");
+    let output = Command::new(env!("CARGO_BIN_EXE_zcc"))
+        .args(&expected.flags)
+        .arg(&source)
+        .arg("-o")
+        .arg(&program)
+        .output()
+        .map_err(|error| error.to_string())?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let code = output.status.code().unwrap_or(-1);
+    let compile =
+        Compile::from_status(code).ok_or_else(|| format!("zcc exited with {code}:\n{stderr}"))?;
+    check_compile(expected, compile, &stderr)?;
+    if !expected.runs() {
+        return Ok(());
+    }
+
+    let output = Command::new(&program)
+        .output()
+        .map_err(|error| format!("running {}: {error}", program.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if let Some(code) = expected.exit {
+        if output.status.code() != Some(code) {
+            return Err(format!("expected exit {code}, got {}", output.status));
+        }
+    }
+    if let Some(expected_stdout) = &expected.stdout {
+        if stdout != *expected_stdout {
+            return Err(format!(
+                "expected stdout:\n{expected_stdout}got stdout:\n{stdout}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_compile(expected: &Expectations, compile: Compile, messages: &str) -> Result<(), String> {
+    match expected.compile {
+        Some(want) if want != compile => Err(format!(
+            "expected compile {want:?}, got {compile:?}\n{messages}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn discover(dir: &Path, cases: &mut Vec<PathBuf>) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|error| panic!("{}: {error}", dir.display()));
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            discover(&path, cases);
+        } else if path.extension().is_some_and(|extension| extension == "c") {
+            cases.push(path);
+        }
+    }
+}
+
+// The parts of libtest's command line that make sense here: name filters,
+// `--skip`, `--exact` and `--list`. Everything else is accepted and ignored,
+// so `cargo test -- <flags>` works across all test targets.
+#[derive(Default)]
+struct Args {
+    filters: Vec<String>,
+    skip: Vec<String>,
+    exact: bool,
+    list: bool,
+}
+
+impl Args {
+    fn parse() -> Args {
+        let mut args = Args::default();
+        let mut argv = env::args().skip(1);
+        while let Some(arg) = argv.next() {
+            match arg.as_str() {
+                "--exact" => args.exact = true,
+                "--list" => args.list = true,
+                "--skip" => args.skip.extend(argv.next()),
+                "--test-threads" | "--format" | "--color" | "-Z" => {
+                    argv.next();
+                }
+                _ if arg.starts_with('-') => {}
+                _ => args.filters.push(arg),
+            }
+        }
+        args
+    }
+
+    fn selects(&self, name: &str) -> bool {
+        let matches = |filter: &String| {
+            if self.exact {
+                name == filter
+            } else {
+                name.contains(filter.as_str())
+            }
+        };
+        (self.filters.is_empty() || self.filters.iter().any(matches))
+            && !self.skip.iter().any(|skip| name.contains(skip.as_str()))
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut paths = vec![];
+    discover(Path::new(CASES), &mut paths);
+    let mut cases: Vec<(String, PathBuf)> = paths
+        .into_iter()
+        .map(|path| {
+            let name = path.strip_prefix(CASES).unwrap().to_string_lossy();
+            (name.replace('\\', "/"), path)
+        })
+        .filter(|(name, _)| args.selects(name))
+        .collect();
+    cases.sort();
+
+    if args.list {
+        for (name, _) in &cases {
+            println!("{name}: test");
+        }
+        return;
+    }
+
+    println!("\nrunning {} tests", cases.len());
+    let (mut passed, mut ignored, mut failures) = (0, 0, vec![]);
+    for (name, path) in &cases {
+        match run_case(name, path) {
+            Outcome::Passed => {
+                println!("test {name} ... ok");
+                passed += 1;
+            }
+            Outcome::Ignored(reason) => {
+                println!("test {name} ... ignored, {reason}");
+                ignored += 1;
+            }
+            Outcome::Failed(error) => {
+                println!("test {name} ... FAILED");
+                failures.push((name, error));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:\n");
+        for (name, error) in &failures {
+            println!("---- {name} ----\n{error}\n");
+        }
+        println!("failures:");
+        for (name, _) in &failures {
+            println!("    {name}");
+        }
+    }
+    let result = if failures.is_empty() { "ok" } else { "FAILED" };
+    println!(
+        "\ntest result: {result}. {passed} passed; {} failed; {ignored} ignored\n",
+        failures.len()
+    );
+    if !failures.is_empty() {
+        process::exit(101);
+    }
+}
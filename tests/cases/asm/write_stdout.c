@@ -0,0 +1,8 @@
+// compile: ok
+// exit: 0
+// stdout: hello
+// target: linux
+int main(void) {
+    __asm__("movq $1, %rax; movq $1, %rdi; leaq 2f(%rip), %rsi; movq $6, %rdx; syscall; jmp 1f; 2: .byte 104, 101, 108, 108, 111, 10; 1:");
+    return 0;
+}
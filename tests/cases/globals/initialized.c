@@ -0,0 +1,8 @@
+// compile: ok
+// exit: 0
+int uninitialized;
+int initialized = 3;
+
+int main(void) {
+    return 0;
+}
@@ -0,0 +1,5 @@
+// compile: lex-error
+// flags: --max-identifier-length 3
+int main(void) {
+    return 0;
+}
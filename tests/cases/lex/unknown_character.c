@@ -0,0 +1,4 @@
+// compile: lex-error
+int main(void) {
+    return @;
+}
@@ -0,0 +1,4 @@
+// compile: parse-error
+return main(void) {
+    return 2;
+}
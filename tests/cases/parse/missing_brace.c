@@ -0,0 +1,4 @@
+// compile: parse-error
+int main(void)
+    return 2;
+}
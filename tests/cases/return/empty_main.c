@@ -0,0 +1,4 @@
+// compile: ok
+// exit: 0
+int main(void) {
+}
@@ -0,0 +1,9 @@
+// compile: ok
+// exit: 4
+int helper(void) {
+    __asm__("nop");
+}
+
+int main(void) {
+    return 4;
+}
@@ -0,0 +1,9 @@
+// compile: ok
+// exit: 5
+void helper(void) {
+    return;
+}
+
+int main(void) {
+    return 5;
+}
@@ -0,0 +1,4 @@
+// compile: semantic-error
+int foo(int);
+int foo(void) { return 1; }
+int main(void) { return 2; }
@@ -0,0 +1,4 @@
+// compile: semantic-error
+int main(void) {
+    return 2147483648;
+}
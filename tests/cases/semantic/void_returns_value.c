@@ -0,0 +1,4 @@
+// compile: semantic-error
+void f(void) {
+    return 1;
+}
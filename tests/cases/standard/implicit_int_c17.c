@@ -0,0 +1,4 @@
+// compile: parse-error
+main(void) {
+    return 3;
+}
@@ -0,0 +1,6 @@
+// compile: ok
+// exit: 3
+// flags: --std c89
+main(void) {
+    return 3;
+}
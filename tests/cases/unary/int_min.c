@@ -0,0 +1,4 @@
+// compile: ok
+int main(void) {
+    return -2147483648;
+}
@@ -0,0 +1,5 @@
+// compile: ok
+// exit: 2
+int main(void) {
+    return ~-3;
+}
@@ -0,0 +1,5 @@
+// compile: ok
+// exit: 1
+int main(void) {
+    return !(0);
+}
@@ -0,0 +1,14 @@
+// Helpers shared by the test targets that run external tools.
+
+use std::process::{Command, Stdio};
+
+/// Whether `tool` can be started at all. Tests that need it skip themselves
+/// when it can't, so they pass on machines without a C toolchain.
+pub fn installed(tool: &str) -> bool {
+    Command::new(tool)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
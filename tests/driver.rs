@@ -0,0 +1,388 @@
+// Flags of the driver binary that only show in the files it leaves behind
+// and its exit status.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use common::installed;
+
+mod common;
+
+/// A scratch directory holding `files`, removed again when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(name: &str, files: &[(&str, &str)]) -> Scratch {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join(file), text).unwrap();
+        }
+        Scratch(dir)
+    }
+
+    fn zcc(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_zcc"))
+            .current_dir(&self.0)
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// The names of the files in `dir` under the scratch directory.
+    fn files(&self, dir: &str) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(self.0.join(dir))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+const VALID: &str = "int main(void) {\n    return 2;\n}\n";
+const INVALID: &str = "int main(void) {\n    return 2\n}\n";
+
+#[test]
+fn fsyntax_only_writes_nothing() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new(
+        "fsyntax-only",
+        &[("valid.c", VALID), ("invalid.c", INVALID)],
+    );
+
+    let output = scratch.zcc(&["-fsyntax-only", "valid.c"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(scratch.files(""), ["invalid.c", "valid.c"]);
+
+    let output = scratch.zcc(&["-fsyntax-only", "invalid.c"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("invalid.c:3:1: expected Semicolon"),
+        "{stderr}"
+    );
+    assert_eq!(scratch.files(""), ["invalid.c", "valid.c"]);
+}
+
+#[test]
+fn trace_dir_holds_every_stage() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new("trace-dir", &[("valid.c", VALID)]);
+    let output = scratch.zcc(&["--emit", "asm", "--trace-dir", "trace", "valid.c"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    assert_eq!(
+        scratch.files("trace"),
+        [
+            "01-tokens.txt",
+            "02-cst.txt",
+            "05-tacky.txt",
+            "07-asm-ir.txt",
+            "08-final.s"
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(scratch.0.join("trace/08-final.s")).unwrap(),
+        fs::read_to_string(scratch.0.join("valid.s")).unwrap()
+    );
+}
+
+#[test]
+fn debug_info_points_at_the_source() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new("debug-info", &[("valid.c", VALID)]);
+    let output = scratch.zcc(&["-g", "--emit", "asm", "valid.c"]);
+    assert_eq!(output.status.code(), Some(0));
+    let assembly = fs::read_to_string(scratch.0.join("valid.s")).unwrap();
+    assert!(assembly.contains("\t.file\t1 \"valid.c\"\n"), "{assembly}");
+    assert!(assembly.contains("\t.loc\t1 2 5\n"), "{assembly}");
+
+    let output = scratch.zcc(&["-g", "valid.c", "-o", "valid"]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("gcc -g valid.s -o valid"), "{stdout}");
+}
+
+#[test]
+fn sarif_log_has_a_result_per_diagnostic() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let source = "f(void) {\n    return 1;\n}\nint g(void) {\n    __asm__(\"nop\");\n}\n";
+    let scratch = Scratch::new("sarif", &[("two.c", source)]);
+    let output = scratch.zcc(&[
+        "--std",
+        "c89",
+        "--diagnostics-format",
+        "sarif",
+        "-fsyntax-only",
+        "two.c",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(log.starts_with("{\n"), "{log}");
+    assert_eq!(log.matches("\"ruleId\"").count(), 2, "{log}");
+    let region = |line, col| {
+        format!(
+            "{{\"artifactLocation\": {{\"uri\": \"two.c\"}}, \"region\": {{\"startLine\": {line}, \
+             \"startColumn\": {col}, \"endLine\": {line}, \"endColumn\": {}}}}}",
+            col + 1
+        )
+    };
+    let implicit_int = log.find("\"ruleId\": \"W0001\"").unwrap();
+    let missing_return = log.find("\"ruleId\": \"W0002\"").unwrap();
+    assert!(log[implicit_int..].contains(&region(1, 1)), "{log}");
+    assert!(log[missing_return..].contains(&region(6, 1)), "{log}");
+
+    let output = scratch.zcc(&[
+        "--std",
+        "c89",
+        "--werror-codes",
+        "W0002",
+        "--diagnostics-format",
+        "sarif",
+        "-fsyntax-only",
+        "two.c",
+    ]);
+    assert_eq!(output.status.code(), Some(9));
+    let log = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        log.contains("{\"ruleId\": \"W0002\", \"ruleIndex\": 1, \"level\": \"error\""),
+        "{log}"
+    );
+}
+
+#[test]
+fn unwritable_trace_file_is_an_io_error() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new("trace-dir-unwritable", &[("valid.c", VALID)]);
+    // A directory where the tree should go.
+    fs::create_dir_all(scratch.0.join("trace/02-cst.txt")).unwrap();
+    let output = scratch.zcc(&["--emit", "asm", "--trace-dir", "trace", "valid.c"]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("02-cst.txt"), "{stderr}");
+    assert_eq!(scratch.files(""), ["trace", "valid.c"]);
+}
+
+#[test]
+fn only_the_stage_flags_dump_tokens_and_tree() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new("dumps", &[("valid.c", VALID)]);
+    let stdout = |args: &[&str]| {
+        let output = scratch.zcc(args);
+        assert_eq!(output.status.code(), Some(0), "{args:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let compiled = stdout(&["--emit", "asm", "valid.c"]);
+    assert!(!compiled.contains("Keyword \"int\""), "{compiled}");
+    assert!(!compiled.contains("Program("), "{compiled}");
+    let lexed = stdout(&["--lex", "valid.c"]);
+    assert!(lexed.contains("Keyword \"int\""), "{lexed}");
+    assert!(!lexed.contains("Program("), "{lexed}");
+    let parsed = stdout(&["--parse", "valid.c"]);
+    assert!(!parsed.contains("Keyword \"int\""), "{parsed}");
+    assert!(parsed.contains("Program("), "{parsed}");
+}
+
+const TWO_FUNCTIONS: &str =
+    "int helper(void) {\n    return 1;\n}\n\nint main(void) {\n    return 2;\n}\n";
+
+#[test]
+fn only_emits_the_named_function() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new("only", &[("two.c", TWO_FUNCTIONS)]);
+    let output = scratch.zcc(&["--emit", "asm", "--only", "helper", "two.c"]);
+    assert_eq!(output.status.code(), Some(0));
+    let asm = fs::read_to_string(scratch.0.join("two.s")).unwrap();
+    assert!(asm.contains("helper:"), "{asm}");
+    assert!(!asm.contains("main"), "{asm}");
+
+    let output = scratch.zcc(&["--emit", "asm", "--only", "missing", "two.c"]);
+    assert_eq!(output.status.code(), Some(8));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no function named missing"), "{stderr}");
+}
+
+#[test]
+fn max_stack_refuses_bigger_frames() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    // Twenty temporaries, 80 bytes.
+    let source = format!("int main(void) {{ return {}2; }}\n", "-~".repeat(10));
+    let scratch = Scratch::new("max-stack", &[("deep.c", &source)]);
+    let output = scratch.zcc(&["--emit", "asm", "--max-stack", "64", "deep.c"]);
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("main: stack frame of 80 bytes is over the limit of 64"),
+        "{stderr}"
+    );
+    assert_eq!(scratch.files(""), ["deep.c"]);
+
+    let output = scratch.zcc(&["--emit", "asm", "--max-stack", "80", "deep.c"]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn emit_stackmap_lists_the_temporaries() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let source = "int main(void) {\n    return -~2;\n}\n";
+    let scratch = Scratch::new("stackmap", &[("two.c", source)]);
+    let output = scratch.zcc(&["--emit", "stackmap", "two.c"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "main:\n    tmp.0 -4(%rbp)\n    tmp.1 -8(%rbp)\n"
+    );
+    assert_eq!(scratch.files(""), ["two.c"]);
+}
+
+#[test]
+fn emit_ident_names_the_compiler_first() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new("emit-ident", &[("valid.c", VALID)]);
+    let ident = format!("# zcc {}", env!("CARGO_PKG_VERSION"));
+
+    assert_eq!(
+        scratch.zcc(&["--emit", "asm", "valid.c"]).status.code(),
+        Some(0)
+    );
+    let asm = fs::read_to_string(scratch.0.join("valid.s")).unwrap();
+    assert!(!asm.contains(&ident), "{asm}");
+
+    let output = scratch.zcc(&["--emit", "asm", "--emit-ident", "valid.c"]);
+    assert_eq!(output.status.code(), Some(0));
+    let asm = fs::read_to_string(scratch.0.join("valid.s")).unwrap();
+    assert_eq!(asm.lines().next(), Some(ident.as_str()));
+}
+
+// What the book's test_compiler script relies on: every stage flag exits 0 on
+// a valid program without writing anything, nonzero on a program that fails
+// at that stage, and no flag leaves an executable next to the source.
+#[test]
+fn book_test_script_flags() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new(
+        "book-flags",
+        &[
+            ("valid.c", VALID),
+            ("lex.c", "int main(void) {\n    return @;\n}\n"),
+            ("parse.c", INVALID),
+            (
+                "semantic.c",
+                "int main(void) {\n    return 2147483648;\n}\n",
+            ),
+        ],
+    );
+    let stages = [
+        ("--lex", "lex.c"),
+        ("--parse", "parse.c"),
+        ("--validate", "semantic.c"),
+        ("--tacky", "semantic.c"),
+        ("--codegen", "semantic.c"),
+    ];
+    for (flag, invalid) in stages {
+        assert_eq!(
+            scratch.zcc(&[flag, "valid.c"]).status.code(),
+            Some(0),
+            "{flag}"
+        );
+        assert_ne!(
+            scratch.zcc(&[flag, invalid]).status.code(),
+            Some(0),
+            "{flag}"
+        );
+    }
+    assert_eq!(
+        scratch.files(""),
+        ["lex.c", "parse.c", "semantic.c", "valid.c"]
+    );
+
+    // A full compilation fails on a stray character the same way --lex does.
+    assert_eq!(scratch.zcc(&["lex.c"]).status.code(), Some(1));
+    assert_eq!(scratch.zcc(&["valid.c"]).status.code(), Some(0));
+    let status = Command::new(scratch.0.join("valid")).status().unwrap();
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn werror_codes_fail_with_the_semantic_exit_code() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new(
+        "werror-codes",
+        &[(
+            "unreachable.c",
+            "int main(void) {\n    return 1;\n    return 2;\n}\n",
+        )],
+    );
+    assert_eq!(scratch.zcc(&["unreachable.c"]).status.code(), Some(0));
+    let output = scratch.zcc(&["--werror-codes", "W0004", "unreachable.c"]);
+    assert_eq!(output.status.code(), Some(9));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[W0004]"), "{stderr}");
+}
+
+#[test]
+fn hash_is_the_same_across_compilations() {
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let scratch = Scratch::new("hash", &[("valid.c", VALID)]);
+    let hash = || {
+        let output = scratch.zcc(&["--emit", "asm", "--hash", "valid.c"]);
+        assert_eq!(output.status.code(), Some(0));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let line = stdout.lines().find(|line| line.contains("sha256")).unwrap();
+        line.to_string()
+    };
+    let first = hash();
+    assert!(first.starts_with("valid.s: "), "{first}");
+    assert_eq!(first, hash());
+}
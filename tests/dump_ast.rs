@@ -0,0 +1,53 @@
+// `--dump-ast=dot` is meant to be piped into dot, so stdout must hold the
+// digraph and nothing else.
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use common::installed;
+
+mod common;
+
+#[test]
+fn dump_ast_dot_prints_only_the_digraph() {
+    // The driver preprocesses with gcc.
+    if !installed("gcc") {
+        eprintln!("skipped: gcc isn't installed");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("zcc-dump-ast-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("return_2.c");
+    fs::copy("examples/return_2.c", &source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_zcc"))
+        .arg("--dump-ast=dot")
+        .arg(&source)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    let dot = String::from_utf8(output.stdout).unwrap();
+    assert!(dot.starts_with("digraph {\n"), "{dot}");
+    assert!(dot.ends_with("}\n"), "{dot}");
+    assert_eq!(dot.matches("digraph").count(), 1);
+
+    if !installed("dot") {
+        eprintln!("skipped rendering: graphviz isn't installed");
+        return;
+    }
+    let mut render = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    render
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(dot.as_bytes())
+        .unwrap();
+    assert!(render.wait().unwrap().success());
+}
@@ -0,0 +1,144 @@
+// The C API, called both from Rust and from the C harness, and the header
+// that describes it.
+
+use std::env;
+use std::ffi::{c_char, CStr, CString};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::ptr;
+
+use common::installed;
+use zcc::ffi::{
+    zcc_compile_to_asm, zcc_free_string, ZCC_COMPILE_ERROR, ZCC_INVALID_ARGUMENT, ZCC_OK,
+};
+
+mod common;
+
+/// Run `zcc_compile_to_asm` on `src`, returning the status and copies of both
+/// outputs, which are freed again.
+fn compile(src: *const c_char) -> (i32, Option<String>, Option<String>) {
+    let mut asm = ptr::null_mut();
+    let mut diagnostics = ptr::null_mut();
+    unsafe {
+        let status = zcc_compile_to_asm(src, &mut asm, &mut diagnostics);
+        let take = |s: *mut c_char| {
+            let text = (!s.is_null()).then(|| CStr::from_ptr(s).to_str().unwrap().to_string());
+            zcc_free_string(s);
+            text
+        };
+        (status, take(asm), take(diagnostics))
+    }
+}
+
+#[test]
+fn compiles_valid_source() {
+    let src = CString::new("int main(void) { return 2; }").unwrap();
+    let (status, asm, diagnostics) = compile(src.as_ptr());
+    assert_eq!(status, ZCC_OK);
+    assert!(asm.unwrap().contains("main:"));
+    assert_eq!(diagnostics, None);
+}
+
+#[test]
+fn reports_warnings_next_to_the_assembly() {
+    let src = CString::new("int main(void) { return 1; return 2; }").unwrap();
+    let (status, asm, diagnostics) = compile(src.as_ptr());
+    assert_eq!(status, ZCC_OK);
+    assert!(asm.unwrap().contains("main:"));
+    assert_eq!(
+        diagnostics.unwrap(),
+        "[{\"message\":\"1:28: code after return in main will never be executed\",\
+         \"code\":\"W0004\"}]"
+    );
+}
+
+#[test]
+fn reports_compile_errors_as_json() {
+    let src = CString::new("int main(void) { return 2 }").unwrap();
+    let (status, asm, diagnostics) = compile(src.as_ptr());
+    assert_eq!(status, ZCC_COMPILE_ERROR);
+    assert_eq!(asm, None);
+    let diagnostics = diagnostics.unwrap();
+    assert!(diagnostics.starts_with("[{\"message\":\""), "{diagnostics}");
+    assert!(diagnostics.ends_with("\"}]"), "{diagnostics}");
+}
+
+#[test]
+fn rejects_invalid_arguments() {
+    assert_eq!(compile(ptr::null()), (ZCC_INVALID_ARGUMENT, None, None));
+
+    let not_utf8 = CString::new(vec![0xff, 0xfe]).unwrap();
+    assert_eq!(
+        compile(not_utf8.as_ptr()),
+        (ZCC_INVALID_ARGUMENT, None, None)
+    );
+
+    let src = CString::new("int main(void) { return 2; }").unwrap();
+    let status = unsafe { zcc_compile_to_asm(src.as_ptr(), ptr::null_mut(), ptr::null_mut()) };
+    assert_eq!(status, ZCC_INVALID_ARGUMENT);
+}
+
+#[test]
+fn header_matches_cbindgen() {
+    if !installed("cbindgen") {
+        eprintln!("skipped: cbindgen isn't installed");
+        return;
+    }
+    let generated = env::temp_dir().join(format!("zcc-{}.h", std::process::id()));
+    let status = Command::new("cbindgen")
+        .args(["--config", "cbindgen.toml", "--output"])
+        .arg(&generated)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let header = fs::read_to_string(&generated).unwrap();
+    fs::remove_file(&generated).unwrap();
+
+    if env::var_os("ZCC_BLESS").is_some() {
+        fs::write("include/zcc.h", header).unwrap();
+    } else {
+        assert_eq!(
+            fs::read_to_string("include/zcc.h").unwrap(),
+            header,
+            "include/zcc.h is stale, rerun with ZCC_BLESS=1"
+        );
+    }
+}
+
+#[test]
+fn c_harness_runs() {
+    if !installed("cc") {
+        eprintln!("skipped: cc isn't installed");
+        return;
+    }
+    // A separate target directory keeps this build from waiting on the lock
+    // of the one running the tests.
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--features", "ffi"])
+        .arg("--target-dir")
+        .arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let lib = target.join("debug");
+    let harness = target.join("harness");
+    let status = Command::new("cc")
+        .args(["examples/ffi/harness.c", "-Iinclude", "-lzcc", "-o"])
+        .arg(&harness)
+        .arg("-L")
+        .arg(&lib)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new(&harness)
+        .env("LD_LIBRARY_PATH", &lib)
+        .env("DYLD_LIBRARY_PATH", &lib)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("main:"));
+}